@@ -21,19 +21,21 @@ extern crate font_loader as fonts;
 use fonts::system_fonts;
 
 fn main() {
-	// Enumerate all fonts
-    let sysfonts = system_fonts::query_all();
+    // Enumerate all fonts
+    let sysfonts = system_fonts::query_all().unwrap();
     for string in &sysfonts {
         println!("{}", string);
     }
 
-	let mut property = system_fonts::FontPropertyBuilder::new().monospace().build();
-	let sysfonts = system_fonts::query_specific(&mut property);
-	for string in &sysfonts {
-		println!("Monospaced font: {}", string);
-	}
+    let property = system_fonts::FontPropertyBuilder::new().monospace().build().unwrap();
+    let sysfonts = system_fonts::query_specific(&property).unwrap();
+    for string in &sysfonts {
+        println!("Monospaced font: {}", string);
+    }
 
-	let property = system_fonts::FontPropertyBuilder::new().family("Arial").build();
-	let (font, _) = system_fonts::get(&property).unwrap();
-	println!("{:?}", &font[..50]);
+    let property = system_fonts::FontPropertyBuilder::new().family("Arial").build().unwrap();
+    match system_fonts::get(&property) {
+        Ok((font, _)) => println!("{:?}", &font[..50]),
+        Err(err) => println!("Arial could not be loaded: {}", err),
+    }
 }
@@ -0,0 +1,419 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use property::{Family, GenericFamily, Slant, Stretch};
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The style, weight, stretch and families of a CSS `font` shorthand
+pub(crate) struct Shorthand {
+    pub slant: Slant,
+    pub weight: u16,
+    pub stretch: Stretch,
+    pub families: Vec<Family>,
+}
+
+/// Parses a CSS `font-family` list like `"Helvetica Neue", Arial, sans-serif`. Unquoted names
+/// are identifiers separated by whitespace, and a single identifier may be a generic family
+pub(crate) fn parse_family_list(list: &str) -> Option<Vec<Family>> {
+    let mut families = Vec::new();
+    let mut chars = list.chars().peekable();
+    loop {
+        skip_whitespace(&mut chars);
+        let family = match chars.peek().cloned() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                chars.next();
+                let name = parse_string(&mut chars, quote)?;
+                skip_whitespace(&mut chars);
+                Family::Name(name)
+            }
+            Some(_) => parse_unquoted(&mut chars)?,
+            None => return None,
+        };
+        families.push(family);
+        match chars.next() {
+            None => return Some(families),
+            Some(',') => {}
+            Some(_) => return None,
+        }
+    }
+}
+
+/// Parses a CSS `font` shorthand like `italic bold 12px/1.4 Georgia, serif`. The size and line
+/// height are required by the grammar but aren't part of the result. System font keywords like
+/// `caption` select the system UI family
+pub(crate) fn parse_font(font: &str) -> Option<Shorthand> {
+    let mut shorthand = Shorthand {
+        slant: Slant::Roman,
+        weight: 400,
+        stretch: Stretch::Normal,
+        families: Vec::new(),
+    };
+    let system = ["caption", "icon", "menu", "message-box", "small-caption", "status-bar"];
+    if system.iter().any(|keyword| keyword.eq_ignore_ascii_case(font.trim())) {
+        shorthand.families.push(Family::Generic(GenericFamily::SystemUi));
+        return Some(shorthand);
+    }
+
+    // Style, variant, weight and stretch come in any order before the size
+    let mut rest = font;
+    let (size, line_height) = loop {
+        let (token, remainder) = next_token(rest)?;
+        rest = remainder;
+        let lower = token.to_ascii_lowercase();
+        match &lower[..] {
+            "normal" | "small-caps" => {}
+            "italic" => shorthand.slant = Slant::Italic,
+            "oblique" => {
+                shorthand.slant = Slant::Oblique;
+                if let Some((angle, remainder)) = next_token(rest) {
+                    if is_dimension(angle, &["deg", "grad", "rad", "turn"]) {
+                        rest = remainder;
+                    }
+                }
+            }
+            "bold" | "bolder" => shorthand.weight = 700,
+            "lighter" => shorthand.weight = 100,
+            _ => {
+                let weight = lower.parse::<u16>().ok().filter(|weight| (1..=1000).contains(weight));
+                if let Some(stretch) = stretch_keyword(&lower) {
+                    shorthand.stretch = stretch;
+                } else if let Some(weight) = weight {
+                    shorthand.weight = weight;
+                } else {
+                    match token.find('/') {
+                        Some(slash) => break (&token[..slash], Some(&token[slash + 1..])),
+                        None => break (token, None),
+                    }
+                }
+            }
+        }
+    };
+    if !is_font_size(size) {
+        return None;
+    }
+    // The line height may be separated from the slash and the size by whitespace
+    let line_height = match line_height {
+        Some("") => {
+            let (line_height, remainder) = next_token(rest)?;
+            rest = remainder;
+            Some(line_height)
+        }
+        Some(line_height) => Some(line_height),
+        None => match rest.trim_start().strip_prefix('/') {
+            Some(after) => {
+                let (line_height, remainder) = next_token(after)?;
+                rest = remainder;
+                Some(line_height)
+            }
+            None => None,
+        },
+    };
+    if line_height.is_some_and(|line_height| !is_line_height(line_height)) {
+        return None;
+    }
+    shorthand.families = parse_family_list(rest)?;
+    Some(shorthand)
+}
+
+fn parse_unquoted(chars: &mut Peekable<Chars>) -> Option<Family> {
+    let mut idents: Vec<String> = Vec::new();
+    loop {
+        skip_whitespace(chars);
+        match chars.peek() {
+            None | Some(&',') => break,
+            _ => {}
+        }
+        let mut ident = String::new();
+        // The first characters as written, before escapes are resolved
+        let mut start = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ',' {
+                break;
+            }
+            if c == '"' || c == '\'' {
+                return None;
+            }
+            chars.next();
+            if start.len() < 2 {
+                start.push(c);
+            }
+            if c == '\\' {
+                ident.push(parse_escape(chars)?);
+            } else {
+                ident.push(c);
+            }
+        }
+        // Identifiers can't start with a digit, like the size in "75% 12px serif"
+        let digit = start.trim_start_matches('-').starts_with(|c: char| c.is_ascii_digit());
+        if digit {
+            return None;
+        }
+        idents.push(ident);
+    }
+    if idents.len() == 1 {
+        let keyword = idents[0].to_ascii_lowercase();
+        let generic = match &keyword[..] {
+            "ui-serif" => Some(GenericFamily::Serif),
+            "ui-sans-serif" => Some(GenericFamily::SansSerif),
+            "ui-monospace" => Some(GenericFamily::Monospace),
+            _ => GenericFamily::from_keyword(&keyword),
+        };
+        if let Some(generic) = generic {
+            return Some(Family::Generic(generic));
+        }
+        // CSS-wide keywords can't be family names
+        if ["inherit", "initial", "unset", "revert", "default"].contains(&&keyword[..]) {
+            return None;
+        }
+    }
+    if idents.is_empty() {
+        None
+    } else {
+        Some(Family::Name(idents.join(" ")))
+    }
+}
+
+/// Reads a string up to the closing quote, the opening one already consumed
+fn parse_string(chars: &mut Peekable<Chars>, quote: char) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            c if c == quote => return Some(string),
+            '\n' => return None,
+            '\\' => {
+                // An escaped newline continues the string on the next line
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                } else {
+                    string.push(parse_escape(chars)?);
+                }
+            }
+            c => string.push(c),
+        }
+    }
+}
+
+/// Reads the character escaped after a backslash, either literal or as up to six hex digits
+fn parse_escape(chars: &mut Peekable<Chars>) -> Option<char> {
+    let mut hex = String::new();
+    while hex.len() < 6 {
+        match chars.peek() {
+            Some(&c) if c.is_ascii_hexdigit() => {
+                hex.push(c);
+                chars.next();
+            }
+            _ => break,
+        }
+    }
+    if hex.is_empty() {
+        return chars.next();
+    }
+    // A single whitespace ends the hex digits
+    if chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+    let code = u32::from_str_radix(&hex, 16).ok()?;
+    match code {
+        0 => Some('\u{FFFD}'),
+        _ => Some(char::from_u32(code).unwrap_or('\u{FFFD}')),
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Splits off the next whitespace separated token
+fn next_token(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+    match text.find(char::is_whitespace) {
+        Some(end) => Some((&text[..end], &text[end..])),
+        None => Some((text, "")),
+    }
+}
+
+fn stretch_keyword(keyword: &str) -> Option<Stretch> {
+    match keyword {
+        "ultra-condensed" => Some(Stretch::UltraCondensed),
+        "extra-condensed" => Some(Stretch::ExtraCondensed),
+        "condensed" => Some(Stretch::Condensed),
+        "semi-condensed" => Some(Stretch::SemiCondensed),
+        "semi-expanded" => Some(Stretch::SemiExpanded),
+        "expanded" => Some(Stretch::Expanded),
+        "extra-expanded" => Some(Stretch::ExtraExpanded),
+        "ultra-expanded" => Some(Stretch::UltraExpanded),
+        _ => None,
+    }
+}
+
+/// Splits a number from its unit, e.g. "1.5em" into "1.5" and "em". The number may be signed
+fn split_number(token: &str) -> Option<(&str, &str)> {
+    let digits = token.strip_prefix(&['+', '-'][..]).unwrap_or(token);
+    let sign = token.len() - digits.len();
+    let end = digits.find(|c: char| !c.is_ascii_digit() && c != '.').map_or(token.len(), |end| sign + end);
+    let number = &token[..end];
+    if number.parse::<f32>().is_ok() {
+        Some((number, &token[end..]))
+    } else {
+        None
+    }
+}
+
+fn is_dimension(token: &str, units: &[&str]) -> bool {
+    split_number(token).is_some_and(|(_, unit)| units.iter().any(|u| u.eq_ignore_ascii_case(unit)))
+}
+
+fn is_font_size(token: &str) -> bool {
+    let keywords = ["xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large",
+                    "xxx-large", "larger", "smaller"];
+    if keywords.iter().any(|keyword| keyword.eq_ignore_ascii_case(token)) {
+        return true;
+    }
+    match split_number(token) {
+        Some((number, _)) if number.starts_with('-') => false,
+        // Only zero may omit its unit
+        Some((number, "")) => number.parse::<f32>() == Ok(0.0),
+        Some((_, unit)) => unit == "%" || unit.chars().all(|c| c.is_ascii_alphabetic()),
+        None => false,
+    }
+}
+
+fn is_line_height(token: &str) -> bool {
+    token.eq_ignore_ascii_case("normal") ||
+    split_number(token).is_some_and(|(number, unit)| {
+        !number.starts_with('-') &&
+        (unit.is_empty() || unit == "%" || unit.chars().all(|c| c.is_ascii_alphabetic()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Family {
+        Family::Name(name.to_string())
+    }
+
+    #[test]
+    fn quoted_names_with_escapes() {
+        assert_eq!(parse_family_list(r#""Helvetica \"Neue\"", 'It\'s'"#),
+                   Some(vec![name("Helvetica \"Neue\""), name("It's")]));
+        // Hex escapes end after six digits or a single whitespace
+        assert_eq!(parse_family_list(r#""A\26 B", "\0000411""#), Some(vec![name("A&B"), name("A1")]));
+        assert_eq!(parse_family_list("\"Line\\\ncontinued\""), Some(vec![name("Linecontinued")]));
+        assert_eq!(parse_family_list(r#""Null\0 byte""#), Some(vec![name("Null\u{FFFD}byte")]));
+    }
+
+    #[test]
+    fn unquoted_names() {
+        assert_eq!(parse_family_list("Helvetica   Neue , Arial"),
+                   Some(vec![name("Helvetica Neue"), name("Arial")]));
+        assert_eq!(parse_family_list(r"Font\ Awesome"), Some(vec![name("Font Awesome")]));
+        // Identifiers can't start with a digit unless it is escaped
+        assert_eq!(parse_family_list("Arial, 3D"), None);
+        assert_eq!(parse_family_list("-3D"), None);
+        assert_eq!(parse_family_list(r"\33 D"), Some(vec![name("3D")]));
+    }
+
+    #[test]
+    fn generic_keywords() {
+        assert_eq!(parse_family_list("serif, Sans-Serif, ui-monospace, system-ui"),
+                   Some(vec![Family::Generic(GenericFamily::Serif),
+                             Family::Generic(GenericFamily::SansSerif),
+                             Family::Generic(GenericFamily::Monospace),
+                             Family::Generic(GenericFamily::SystemUi)]));
+        // Quoted or among other identifiers, keywords are names
+        assert_eq!(parse_family_list(r#""serif", monospace Pro"#),
+                   Some(vec![name("serif"), name("monospace Pro")]));
+    }
+
+    #[test]
+    fn invalid_family_lists() {
+        for list in &["", "Arial,", ", Arial", "\"Unclosed", "\"Line\nbreak\"", "Arial \"Quoted\"",
+                      "\"A\" B", "inherit", "Arial, initial"] {
+            assert_eq!(parse_family_list(list), None, "{:?}", list);
+        }
+    }
+
+    fn font(font: &str) -> Shorthand {
+        parse_font(font).unwrap_or_else(|| panic!("{:?} didn't parse", font))
+    }
+
+    #[test]
+    fn weights() {
+        assert_eq!(font("bold 12px serif").weight, 700);
+        assert_eq!(font("bolder 12px serif").weight, 700);
+        assert_eq!(font("lighter 12px serif").weight, 100);
+        assert_eq!(font("350 12px serif").weight, 350);
+        assert_eq!(font("1000 12px serif").weight, 1000);
+        assert_eq!(font("12px serif").weight, 400);
+        // Out of range numbers are taken for the size, which needs a unit
+        assert!(parse_font("1001 12px serif").is_none());
+    }
+
+    #[test]
+    fn stretches() {
+        assert_eq!(font("condensed 12px serif").stretch, Stretch::Condensed);
+        assert_eq!(font("Ultra-Expanded bold 12px serif").stretch, Stretch::UltraExpanded);
+        // The shorthand only takes stretch keywords, a percentage before the family is the size
+        let percentage = font("75% serif");
+        assert_eq!(percentage.stretch, Stretch::Normal);
+        assert_eq!(percentage.families, vec![Family::Generic(GenericFamily::Serif)]);
+        assert!(parse_font("75% 12px serif").is_none());
+    }
+
+    #[test]
+    fn slants() {
+        assert_eq!(font("italic 12px serif").slant, Slant::Italic);
+        assert_eq!(font("oblique 12px serif").slant, Slant::Oblique);
+        let angle = font("oblique 10deg bold 12px serif");
+        assert_eq!((angle.slant, angle.weight), (Slant::Oblique, 700));
+        assert_eq!(font("oblique -0.5turn 12px serif").families, vec![Family::Generic(GenericFamily::Serif)]);
+    }
+
+    #[test]
+    fn size_and_line_height() {
+        for value in &["12px/1.4 Georgia", "12px / 1.4 Georgia", "12px/ 1.4 Georgia", "12px /1.4 Georgia",
+                       "small/normal Georgia", "0/120% Georgia", "2em Georgia"] {
+            assert_eq!(font(value).families, vec![name("Georgia")], "{:?}", value);
+        }
+        assert_eq!(font("italic small-caps bold condensed 16px/2 \"Helvetica Neue\", Arial, sans-serif").families,
+                   vec![name("Helvetica Neue"), name("Arial"), Family::Generic(GenericFamily::SansSerif)]);
+    }
+
+    #[test]
+    fn system_fonts() {
+        assert_eq!(font(" Caption ").families, vec![Family::Generic(GenericFamily::SystemUi)]);
+    }
+
+    #[test]
+    fn invalid_shorthands() {
+        for value in &["", "bold", "Georgia", "12 Georgia", "12px", "12px/", "12px/abc Georgia",
+                       "12px/1.4", "bold 12px \"Unclosed", "12px Arial,", "-12px Georgia",
+                       "12px/-1 Georgia"] {
+            assert!(parse_font(value).is_none(), "{:?}", value);
+        }
+    }
+}
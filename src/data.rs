@@ -0,0 +1,103 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Binary data of a font file, either owned, shared with a `FontDatabase` or memory mapped
+///
+/// Mapped data is shared with every other process mapping the same file. Mappings assume the
+/// file is not modified while they are alive, as is the case for installed system fonts.
+pub struct FontData {
+    inner: Inner,
+}
+
+enum Inner {
+    Owned(Vec<u8>),
+    Shared(Arc<Vec<u8>>),
+    #[cfg(feature = "mmap")]
+    Mapped(Mmap),
+}
+
+impl FontData {
+    pub(crate) fn shared(data: Arc<Vec<u8>>) -> FontData {
+        FontData { inner: Inner::Shared(data) }
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn mapped(map: Mmap) -> FontData {
+        FontData { inner: Inner::Mapped(map) }
+    }
+
+    /// Whether the data is backed by a memory mapping instead of a heap buffer
+    pub fn is_mapped(&self) -> bool {
+        match self.inner {
+            Inner::Owned(_) | Inner::Shared(_) => false,
+            #[cfg(feature = "mmap")]
+            Inner::Mapped(_) => true,
+        }
+    }
+
+    /// Copies the data into a heap buffer, or returns the buffer if it is owned already
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.inner {
+            Inner::Owned(buf) => buf,
+            Inner::Shared(data) => Arc::try_unwrap(data).unwrap_or_else(|data| data.to_vec()),
+            #[cfg(feature = "mmap")]
+            Inner::Mapped(map) => map.to_vec(),
+        }
+    }
+}
+
+impl Deref for FontData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.inner {
+            Inner::Owned(ref buf) => buf,
+            Inner::Shared(ref data) => data,
+            #[cfg(feature = "mmap")]
+            Inner::Mapped(ref map) => map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for FontData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for FontData {
+    fn from(buf: Vec<u8>) -> FontData {
+        FontData { inner: Inner::Owned(buf) }
+    }
+}
+
+impl fmt::Debug for FontData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FontData")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
@@ -0,0 +1,703 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use error::{FontLoaderError, Result};
+use handle::{map_raw, open_file, unwrap_woff, FontHandle, Source};
+use info::{FontInfo, StyleInfo};
+use matching::locate_in_stack;
+use property::{FontFormat, FontProperty, FontPropertyBuilder, GenericFamily, Slant, Spacing};
+use sfnt::{self, Coverage, Face};
+
+use libc::c_int;
+
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Tables describing a face for matching, the only ones read from font files
+const MATCHING_TABLES: [&[u8; 4]; 5] = [b"name", b"OS/2", b"head", b"post", b"cmap"];
+
+/// `ulCodePageRange1` bits of the Chinese code pages
+const CODE_PAGE_GB2312: u32 = 1 << 18;
+const CODE_PAGE_BIG5: u32 = 1 << 20;
+
+/// Fonts collected by the application, matched in Rust independent of the platform backend
+///
+/// System fonts and fonts bundled with the application can be loaded into the same database,
+/// so that they are matched against each other. Only TrueType and OpenType fonts and
+/// collections are supported, and with the `woff` feature WOFF and WOFF2 fonts.
+#[derive(Clone, Default)]
+pub struct FontDatabase {
+    entries: Vec<Entry>,
+}
+
+/// A loaded face, with the tables needed for matching
+#[derive(Clone, PartialEq)]
+struct Entry {
+    source: Source,
+    face: Face,
+}
+
+impl FontDatabase {
+    pub fn new() -> FontDatabase {
+        FontDatabase { entries: Vec::new() }
+    }
+
+    /// Number of faces loaded
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Loads the fonts installed in the platform's font directories. Unreadable files and
+    /// directories are skipped
+    pub fn load_system_fonts(&mut self) {
+        let mut visited = HashSet::new();
+        for dir in system_font_dirs() {
+            let _ = self.scan_dir(&dir, &mut visited);
+        }
+    }
+
+    /// Loads every face of a font file or collection. Only the tables needed for matching are
+    /// read, except from WOFF and WOFF2 fonts which have to be decoded whole
+    pub fn load_font_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let source = Source::File(path.to_path_buf());
+        let mut file = open_file(path)?;
+        let mut magic = Vec::new();
+        (&mut file).take(4).read_to_end(&mut magic)?;
+        if magic == b"wOFF" || magic == b"wOF2" {
+            let data = map_raw(path)?;
+            let format = FontFormat::detect(&data);
+            return self.add_faces(source, &unwrap_woff(data)?, format);
+        }
+        let faces = sfnt::read_faces(&mut file, &MATCHING_TABLES)?;
+        self.push_faces(source, faces, None)
+    }
+
+    /// Loads every face of a font or collection held in memory. With the `woff` feature, WOFF
+    /// and WOFF2 fonts are decoded first
+    pub fn load_font_data(&mut self, data: Vec<u8>) -> Result<()> {
+        let format = FontFormat::detect(&data);
+        let data = Arc::new(unwrap_woff(data)?);
+        self.add_faces(Source::Memory(data.clone()), &data, format)
+    }
+
+    /// Loads the font files found in the directory and its subdirectories, recognized by their
+    /// extension. Files which can't be read are skipped
+    pub fn load_fonts_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        self.scan_dir(path, &mut HashSet::new()).map_err(|err| {
+            if err.kind() == ErrorKind::NotFound {
+                FontLoaderError::MissingFile(path.to_path_buf())
+            } else {
+                FontLoaderError::Io(err)
+            }
+        })
+    }
+
+    /// Whether both databases hold the same faces in the same order
+    #[cfg(all(unix, not(target_os = "macos"), not(feature = "fontconfig")))]
+    pub(crate) fn same_faces(&self, other: &FontDatabase) -> bool {
+        self.entries == other.entries
+    }
+
+    /// Adds the faces of the decoded font, read from a file of the format
+    fn add_faces(&mut self, source: Source, data: &[u8], format: Option<FontFormat>) -> Result<()> {
+        self.push_faces(source, sfnt::parse_faces(data), format)
+    }
+
+    /// Adds the parsed faces of a font read from a file of the format
+    fn push_faces(&mut self, source: Source, faces: Vec<Face>, format: Option<FontFormat>) -> Result<()> {
+        if faces.is_empty() {
+            return Err(FontLoaderError::UnsupportedFormat);
+        }
+        for mut face in faces {
+            if let Some(web @ FontFormat::Woff) | Some(web @ FontFormat::Woff2) = format {
+                face.format = web;
+            }
+            self.entries.push(Entry { source: source.clone(), face });
+        }
+        Ok(())
+    }
+
+    /// The entries in one of the property's formats. They are all scalable, so none are left
+    /// when bitmap fonts are requested
+    fn candidates<'a>(&'a self, property: &'a FontProperty) -> impl Iterator<Item = &'a Entry> + 'a {
+        self.entries.iter().filter(move |entry| {
+            property.allows_format(Some(entry.face.format)) && property.allows_scalable(true)
+        })
+    }
+
+    fn scan_dir(&mut self, dir: &Path, visited: &mut HashSet<PathBuf>) -> io::Result<()> {
+        // Directories are tracked by their canonical path, so symlink loops are walked once
+        if !visited.insert(fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                let _ = self.scan_dir(&path, visited);
+            } else if is_font_file(&path) {
+                let _ = self.load_font_file(&path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the binary data and index of a specific font
+    pub fn get(&self, property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        let handle = self.locate(property)?;
+        Ok((handle.load()?, handle.index()))
+    }
+
+    /// Resolve the source and face index of a specific font without reading it. Only faces
+    /// containing all the property's characters match
+    pub fn locate(&self, property: &FontProperty) -> Result<FontHandle> {
+        let property = &self.resolve_generics(property);
+        self.candidates(property)
+            .filter(|entry| property.chars().iter().all(|&c| entry.face.coverage.contains(c)))
+            .min_by_key(|entry| score(&entry.face, property))
+            .map(to_handle)
+            .ok_or(FontLoaderError::NoMatch)
+    }
+
+    /// Find the best face containing the character, preferring faces close to the requested
+    /// properties
+    pub fn fallback_for(&self, c: char, property: &FontProperty) -> Result<FontHandle> {
+        let property = &self.resolve_generics(property);
+        self.candidates(property)
+            .filter(|entry| entry.face.coverage.contains(c))
+            .min_by_key(|entry| score(&entry.face, property))
+            .map(to_handle)
+            .ok_or(FontLoaderError::NoMatch)
+    }
+
+    /// Get all faces ordered from best to worst match, as a fallback list for shaping text.
+    /// With `trim`, faces which don't contribute characters missing from the faces before them
+    /// are left out
+    pub fn match_sorted(&self, property: &FontProperty, trim: bool) -> Result<Vec<FontHandle>> {
+        let property = &self.resolve_generics(property);
+        let mut sorted: Vec<&Entry> = self.candidates(property).collect();
+        sorted.sort_by_key(|entry| score(&entry.face, property));
+
+        let mut covered = Coverage::default();
+        let mut handles = Vec::new();
+        for entry in sorted {
+            if trim {
+                if entry.face.coverage.is_subset(&covered) {
+                    continue;
+                }
+                covered.union(&entry.face.coverage);
+            }
+            handles.push(to_handle(entry));
+        }
+        Ok(handles)
+    }
+
+    /// Query the names of all font families in the database
+    pub fn query_all(&self) -> Result<Vec<String>> {
+        let property = FontPropertyBuilder::new().build()?;
+        self.query_specific(&property)
+    }
+
+    /// Query the names of the font families with faces matching the property exactly
+    pub fn query_specific(&self, property: &FontProperty) -> Result<Vec<String>> {
+        let property = &self.resolve_generics(property);
+        let mut fonts: Vec<String> = self.entries
+            .iter()
+            .map(|entry| &entry.face)
+            .filter(|face| is_listed(face, property))
+            .filter(|face| {
+                face.weight == property.weight() && face.slant == property.slant() &&
+                face.stretch == property.stretch()
+            })
+            .map(|face| face.family.clone())
+            .collect();
+        fonts.sort();
+        fonts.dedup();
+        Ok(fonts)
+    }
+
+    /// Query every face of the fonts matching the property's families and spacing.
+    /// Weight, slant and stretch are ignored, so that all styles of a family are returned
+    pub fn query_faces(&self, property: &FontProperty) -> Result<Vec<FontInfo>> {
+        let property = &self.resolve_generics(property);
+        let mut faces: Vec<FontInfo> = self.entries
+            .iter()
+            .filter(|entry| is_listed(&entry.face, property))
+            .map(to_info)
+            .collect();
+        faces.sort_by(|a, b| {
+            (&a.family, a.stretch, a.weight, a.slant, a.path(), a.index)
+                .cmp(&(&b.family, b.stretch, b.weight, b.slant, b.path(), b.index))
+        });
+        Ok(faces)
+    }
+
+    /// The styles of the family in the database, e.g. to let users pick one by name for
+    /// `FontPropertyBuilder::style`
+    pub fn styles_of(&self, family: &str) -> Result<Vec<StyleInfo>> {
+        let property = FontPropertyBuilder::new().family(family).build()?;
+        Ok(StyleInfo::of_faces(&self.query_faces(&property)?))
+    }
+
+    /// Get the binary data and index of the first family of the property in the database, as
+    /// CSS walks a font stack. Unlike `get`, no other family is substituted, so this fails with
+    /// `NoMatch` if none of them is in the database
+    pub fn get_first_available(&self, property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        if property.families().is_empty() {
+            return self.get(property);
+        }
+        for single in property.family_stack(|generic| self.resolve_generic(generic)) {
+            if !self.query_faces(&single)?.is_empty() {
+                return self.get(&single);
+            }
+        }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Resolve the source and face index of the face CSS Fonts Level 4 font matching selects,
+    /// among the faces of the first family of the property in the database
+    pub fn locate_css(&self, property: &FontProperty) -> Result<FontHandle> {
+        locate_in_stack(property, |generic| self.resolve_generic(generic), || self.locate(property),
+                        |single| self.query_faces(single))
+    }
+
+    /// Get the binary data and index of the face with the PostScript name in its name table,
+    /// such as `Roboto-BoldItalic`. No other face is substituted, names are compared ignoring
+    /// ASCII case
+    pub fn get_by_postscript_name(&self, name: &str) -> Result<(Vec<u8>, c_int)> {
+        self.get_by_name(name, |face| &face.postscript_name)
+    }
+
+    /// Get the binary data and index of the face with the full name in its name table, such
+    /// as `Roboto Bold Italic`. No other face is substituted, names are compared ignoring
+    /// ASCII case
+    pub fn get_by_full_name(&self, name: &str) -> Result<(Vec<u8>, c_int)> {
+        self.get_by_name(name, |face| &face.full_name)
+    }
+
+    fn get_by_name<F: Fn(&Face) -> &str>(&self, name: &str, face_name: F) -> Result<(Vec<u8>, c_int)> {
+        let handle = self.entries
+            .iter()
+            .find(|entry| !name.is_empty() && face_name(&entry.face).eq_ignore_ascii_case(name))
+            .map(to_handle)
+            .ok_or(FontLoaderError::NoMatch)?;
+        Ok((handle.load()?, handle.index()))
+    }
+
+    /// Resolve a generic family to the families in the database commonly used for it, most
+    /// preferred first. Without any of those, monospace and emoji fall back on the faces'
+    /// spacing and coverage
+    pub fn resolve_generic(&self, generic: GenericFamily) -> Vec<String> {
+        let mut families = Vec::new();
+        for candidate in default_families(generic) {
+            let found = self.entries.iter().find(|entry| entry.face.family.eq_ignore_ascii_case(candidate));
+            if let Some(entry) = found {
+                if !families.contains(&entry.face.family) {
+                    families.push(entry.face.family.clone());
+                }
+            }
+        }
+        if families.is_empty() {
+            families = self.entries
+                .iter()
+                .map(|entry| &entry.face)
+                .filter(|face| match generic {
+                    GenericFamily::Monospace => face.spacing == Spacing::Monospace,
+                    GenericFamily::Emoji => face.coverage.contains('\u{1F600}'),
+                    _ => false,
+                })
+                .map(|face| face.family.clone())
+                .collect();
+            families.sort();
+            families.dedup();
+        }
+        families
+    }
+
+    fn resolve_generics(&self, property: &FontProperty) -> FontProperty {
+        property.resolve_generics(|generic| self.resolve_generic(generic))
+    }
+}
+
+impl fmt::Debug for FontDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FontDatabase")
+            .field("faces", &self.entries.len())
+            .finish()
+    }
+}
+
+/// Font directories of the XDG Base Directory specification, most specific first
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn system_font_dirs() -> Vec<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = Vec::new();
+    match env::var_os("XDG_DATA_HOME") {
+        Some(ref data_home) if !data_home.is_empty() => dirs.push(Path::new(data_home).join("fonts")),
+        _ => dirs.extend(home.iter().map(|home| home.join(".local/share/fonts"))),
+    }
+    dirs.extend(home.iter().map(|home| home.join(".fonts")));
+
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|data_dirs| !data_dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    dirs.extend(env::split_paths(&data_dirs).map(|dir| dir.join("fonts")));
+    dirs.push(PathBuf::from("/usr/share/fonts"));
+    dirs
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("HOME")
+        .map(|home| Path::new(&home).join("Library/Fonts"))
+        .into_iter()
+        .collect();
+    for dir in &["/Library/Fonts", "/Network/Library/Fonts", "/System/Library/Fonts"] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn system_font_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("LOCALAPPDATA")
+        .map(|local| Path::new(&local).join("Microsoft\\Windows\\Fonts"))
+        .into_iter()
+        .collect();
+    let windir = env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+    dirs.push(Path::new(&windir).join("Fonts"));
+    dirs
+}
+
+/// Families commonly used for a generic family across platforms, most preferred first
+pub(crate) fn default_families(generic: GenericFamily) -> &'static [&'static str] {
+    match generic {
+        GenericFamily::Serif => {
+            &["DejaVu Serif", "Noto Serif", "Liberation Serif", "Times New Roman", "Times", "Georgia"]
+        }
+        GenericFamily::SansSerif => {
+            &["DejaVu Sans", "Noto Sans", "Liberation Sans", "Arial", "Helvetica", "Verdana"]
+        }
+        GenericFamily::Monospace => {
+            &["DejaVu Sans Mono", "Noto Sans Mono", "Liberation Mono", "Consolas", "Menlo",
+              "Courier New", "Courier"]
+        }
+        GenericFamily::Cursive => &["Comic Neue", "Comic Sans MS", "Apple Chancery", "Z003"],
+        GenericFamily::Fantasy => &["Impact", "Papyrus", "Luminari"],
+        GenericFamily::Emoji => {
+            &["Noto Color Emoji", "Apple Color Emoji", "Segoe UI Emoji", "Twemoji Mozilla"]
+        }
+        GenericFamily::SystemUi => {
+            &["Cantarell", "Ubuntu", "Noto Sans", "DejaVu Sans", "Segoe UI", "Helvetica Neue"]
+        }
+    }
+}
+
+fn is_font_file(path: &Path) -> bool {
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return false,
+    };
+    ["ttf", "otf", "ttc", "otc"].contains(&&extension[..]) ||
+    (cfg!(feature = "woff") && ["woff", "woff2"].contains(&&extension[..]))
+}
+
+/// Whether a listing with the property includes the face, ignoring its style
+fn is_listed(face: &Face, property: &FontProperty) -> bool {
+    (property.families().is_empty() || family_rank(face, property) < property.families().len()) &&
+    property.allows_format(Some(face.format)) && property.allows_scalable(true) &&
+    property.spacing().is_none_or(|spacing| spacing == face.spacing) &&
+    property.chars().iter().all(|&c| face.coverage.contains(c)) &&
+    property.language().is_none_or(|language| supports_language(face, language))
+}
+
+/// Sort key of a face for the property, lower is better. The priorities follow fontconfig:
+/// coverage, family, language, spacing, style name, slant, weight and finally stretch
+fn score(face: &Face, property: &FontProperty) -> (usize, usize, bool, bool, bool, u8, u16, u16) {
+    let missing = property.chars().iter().filter(|&&c| !face.coverage.contains(c)).count();
+    let language = property.language().is_none_or(|language| supports_language(face, language));
+    let spacing = property.spacing().is_none_or(|spacing| spacing == face.spacing);
+    let style = property.style().is_none_or(|style| style.eq_ignore_ascii_case(&face.style));
+    let slant = match (property.slant(), face.slant) {
+        (requested, actual) if requested == actual => 0,
+        (Slant::Roman, Slant::Oblique) | (Slant::Italic, Slant::Oblique) |
+        (Slant::Oblique, Slant::Italic) => 1,
+        _ => 2,
+    };
+    let weight = property.weight().abs_diff(face.weight);
+    let stretch = property.stretch().percentage().abs_diff(face.stretch.percentage());
+    (missing, family_rank(face, property), !language, !spacing, !style, slant, weight, stretch)
+}
+
+/// Position of the face's family among the requested ones, their count if not requested
+fn family_rank(face: &Face, property: &FontProperty) -> usize {
+    property.families()
+        .iter()
+        .position(|family| family.name().eq_ignore_ascii_case(&face.family))
+        .unwrap_or_else(|| property.families().len())
+}
+
+/// Whether the face can display the language, judged by sample characters of its script.
+/// Chinese faces are further told apart by the code pages they declare
+fn supports_language(face: &Face, tag: &str) -> bool {
+    let tag = tag.to_ascii_lowercase().replace('_', "-");
+    let mut subtags = tag.split('-');
+    let primary = subtags.next().unwrap_or("");
+    let samples = match primary {
+        "ja" => "あア日本",
+        "ko" => "한국어",
+        "zh" => "中文",
+        "ru" | "uk" | "be" | "bg" | "mk" | "kk" => "абвгд",
+        "el" => "αβγδ",
+        "he" | "yi" => "אבג",
+        "ar" | "fa" | "ur" => "ابت",
+        "th" => "กขค",
+        "hi" | "mr" | "ne" => "कखग",
+        _ => "abc",
+    };
+    if !samples.chars().all(|c| face.coverage.contains(c)) {
+        return false;
+    }
+    if primary == "zh" && face.code_pages & (CODE_PAGE_GB2312 | CODE_PAGE_BIG5) != 0 {
+        let traditional = subtags.any(|subtag| ["hant", "tw", "hk", "mo"].contains(&subtag));
+        let code_page = if traditional { CODE_PAGE_BIG5 } else { CODE_PAGE_GB2312 };
+        return face.code_pages & code_page != 0;
+    }
+    true
+}
+
+fn to_handle(entry: &Entry) -> FontHandle {
+    FontHandle::new(entry.source.clone(), entry.face.index as c_int, entry.face.family.clone(),
+                    entry.face.style.clone())
+}
+
+fn to_info(entry: &Entry) -> FontInfo {
+    let face = &entry.face;
+    FontInfo {
+        family: face.family.clone(),
+        style: face.style.clone(),
+        full_name: face.full_name.clone(),
+        postscript_name: face.postscript_name.clone(),
+        weight: face.weight,
+        slant: face.slant,
+        stretch: face.stretch,
+        spacing: face.spacing,
+        source: entry.source.clone(),
+        index: face.index as c_int,
+        format: Some(face.format),
+        scalable: true,
+        pixel_size: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use property::Stretch;
+    use sfnt::build_sfnt;
+    use sfnt::tests::{cmap_table, name_table, os2_table, post_table, tag};
+
+    const ITALIC: u16 = 1;
+    const OBLIQUE: u16 = 1 << 9;
+
+    fn font(family: &str, style: &str, os2: Vec<u8>, monospace: bool, chars: &str) -> Vec<u8> {
+        let mut ranges: Vec<(u32, u32)> = chars.chars().map(|c| (c as u32, c as u32)).collect();
+        ranges.sort();
+        let (name, post, cmap) = (name_table(family, style), post_table(monospace), cmap_table(&ranges));
+        build_sfnt(0x0001_0000, vec![(tag(b"name"), &name), (tag(b"OS/2"), &os2), (tag(b"post"), &post),
+                                     (tag(b"cmap"), &cmap)])
+    }
+
+    /// A proportional face covering "abc"
+    fn face(family: &str, style: &str, weight: u16, width_class: u16, selection: u16) -> Vec<u8> {
+        font(family, style, os2_table(weight, width_class, selection, 0), false, "abc")
+    }
+
+    fn load(fonts: Vec<Vec<u8>>) -> FontDatabase {
+        let mut database = FontDatabase::new();
+        for data in fonts {
+            database.load_font_data(data).unwrap();
+        }
+        database
+    }
+
+    /// Style of the located face
+    fn located(database: &FontDatabase, property: FontPropertyBuilder) -> String {
+        database.locate(&property.build().unwrap()).unwrap().style().to_string()
+    }
+
+    #[test]
+    fn font_files() {
+        let path = env::temp_dir().join(format!("font-loader-database-{}.ttf", ::std::process::id()));
+        let data = font("A", "Bold", os2_table(700, 3, ITALIC, 0), true, "abc");
+        fs::write(&path, &data).unwrap();
+        let mut from_file = FontDatabase::new();
+        let loaded = from_file.load_font_file(&path);
+        fs::write(&path, b"not a font").unwrap();
+        let invalid = from_file.load_font_file(&path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap();
+        assert!(matches!(invalid, Err(FontLoaderError::UnsupportedFormat)));
+        assert!(matches!(from_file.load_font_file(&path), Err(FontLoaderError::MissingFile(_))));
+
+        // Reading only the tables needed describes the faces just like loading the whole font
+        let from_data = load(vec![data]);
+        assert_eq!(from_file.entries.len(), 1);
+        assert_eq!(from_file.entries[0].face, from_data.entries[0].face);
+        assert_eq!(from_file.entries[0].source, Source::File(path));
+    }
+
+    #[test]
+    fn family_rank() {
+        let database = load(vec![face("A", "A", 400, 5, 0), face("B", "B", 400, 5, 0)]);
+        assert_eq!(located(&database, FontPropertyBuilder::new()), "A");
+        assert_eq!(located(&database, FontPropertyBuilder::new().css_family("B, A")), "B");
+        assert_eq!(located(&database, FontPropertyBuilder::new().css_family("Missing, A")), "A");
+        // The family comes before the style
+        assert_eq!(located(&database, FontPropertyBuilder::new().family("b").bold().italic()), "B");
+    }
+
+    #[test]
+    fn weight_and_stretch() {
+        let database = load(vec![
+            face("A", "Light", 300, 5, 0),
+            face("A", "Medium", 500, 5, 0),
+            face("A", "Bold", 700, 5, 0),
+            face("A", "Bold Condensed", 700, 3, 0),
+        ]);
+        // Ties go to the face loaded first
+        assert_eq!(located(&database, FontPropertyBuilder::new().weight(400)), "Light");
+        assert_eq!(located(&database, FontPropertyBuilder::new().weight(600)), "Medium");
+        assert_eq!(located(&database, FontPropertyBuilder::new().bold()), "Bold");
+        assert_eq!(located(&database, FontPropertyBuilder::new().bold().condensed()), "Bold Condensed");
+        assert_eq!(located(&database, FontPropertyBuilder::new().bold().stretch(Stretch::SemiExpanded)),
+                   "Bold");
+        // The weight comes before the stretch
+        assert_eq!(located(&database, FontPropertyBuilder::new().medium().condensed()), "Medium");
+
+        let database = load(vec![face("A", "Condensed", 400, 3, 0), face("A", "Expanded", 400, 7, 0)]);
+        assert_eq!(located(&database, FontPropertyBuilder::new()), "Condensed");
+        assert_eq!(located(&database, FontPropertyBuilder::new().stretch(Stretch::SemiExpanded)), "Expanded");
+    }
+
+    #[test]
+    fn slant_before_weight() {
+        let database = load(vec![
+            face("A", "Regular", 400, 5, 0),
+            face("A", "Bold Italic", 700, 5, ITALIC),
+            face("A", "Oblique", 400, 5, OBLIQUE),
+        ]);
+        assert_eq!(located(&database, FontPropertyBuilder::new()), "Regular");
+        assert_eq!(located(&database, FontPropertyBuilder::new().italic()), "Bold Italic");
+        assert_eq!(located(&database, FontPropertyBuilder::new().oblique()), "Oblique");
+        assert_eq!(located(&database, FontPropertyBuilder::new().style("bold italic")), "Bold Italic");
+    }
+
+    #[test]
+    fn character_coverage() {
+        let os2 = || os2_table(400, 5, 0, 0);
+        let database = load(vec![
+            font("A", "A", os2(), false, "abc"),
+            font("B", "B", os2(), false, "ab"),
+            font("C", "C", os2(), false, "abc中"),
+        ]);
+        // Missing characters come before the family
+        assert_eq!(located(&database, FontPropertyBuilder::new().family("A").covers_char('中')), "C");
+        let uncovered = FontPropertyBuilder::new().covers_char('\u{378}').build().unwrap();
+        assert!(matches!(database.locate(&uncovered), Err(FontLoaderError::NoMatch)));
+
+        let property = FontPropertyBuilder::new().family("B").build().unwrap();
+        assert_eq!(database.fallback_for('中', &property).unwrap().style(), "C");
+        assert!(matches!(database.fallback_for('\u{378}', &property), Err(FontLoaderError::NoMatch)));
+
+        let sorted = |family, trim| -> Vec<String> {
+            let property = FontPropertyBuilder::new().family(family).build().unwrap();
+            database.match_sorted(&property, trim)
+                .unwrap()
+                .iter()
+                .map(|handle| handle.style().to_string())
+                .collect()
+        };
+        assert_eq!(sorted("B", false), ["B", "A", "C"]);
+        assert_eq!(sorted("B", true), ["B", "A", "C"]);
+        // Faces adding no characters are trimmed
+        assert_eq!(sorted("C", false), ["C", "A", "B"]);
+        assert_eq!(sorted("C", true), ["C"]);
+    }
+
+    #[test]
+    fn generic_families() {
+        let os2 = || os2_table(400, 5, 0, 0);
+        let database = load(vec![
+            font("Code", "Regular", os2(), true, "abc"),
+            font("DejaVu Sans Mono", "Book", os2(), false, "abc"),
+            font("Text", "Regular", os2(), false, "abc\u{1F600}"),
+        ]);
+        assert_eq!(database.resolve_generic(GenericFamily::Monospace), ["DejaVu Sans Mono"]);
+        assert_eq!(database.resolve_generic(GenericFamily::Emoji), ["Text"]);
+        assert!(database.resolve_generic(GenericFamily::Serif).is_empty());
+
+        // Without a common family, monospace falls back on the spacing
+        let database = load(vec![font("Text", "Regular", os2(), false, "abc"),
+                                     font("Code", "Regular", os2(), true, "abc")]);
+        assert_eq!(database.resolve_generic(GenericFamily::Monospace), ["Code"]);
+        assert_eq!(located(&database, FontPropertyBuilder::new().generic(GenericFamily::Monospace)),
+                   "Regular");
+        let property = FontPropertyBuilder::new().generic(GenericFamily::Monospace).build().unwrap();
+        assert_eq!(database.locate(&property).unwrap().family(), "Code");
+    }
+
+    #[test]
+    fn listing() {
+        let font = |family, code_pages, chars| {
+            font(family, "Regular", os2_table(400, 5, 0, code_pages), false, chars)
+        };
+        let database = load(vec![
+            font("Latin", 0, "abc"),
+            font("Cyrillic", 0, "abcабвгд"),
+            font("Hans", CODE_PAGE_GB2312, "abc中文"),
+            font("Hant", CODE_PAGE_BIG5, "abc中文"),
+        ]);
+        let query = |property: FontPropertyBuilder| {
+            database.query_specific(&property.build().unwrap()).unwrap()
+        };
+        assert_eq!(database.query_all().unwrap(), ["Cyrillic", "Hans", "Hant", "Latin"]);
+        assert_eq!(query(FontPropertyBuilder::new().language("ru")), ["Cyrillic"]);
+        assert_eq!(query(FontPropertyBuilder::new().language("zh")), ["Hans"]);
+        assert_eq!(query(FontPropertyBuilder::new().language("zh-Hant")), ["Hant"]);
+        assert_eq!(query(FontPropertyBuilder::new().covers_str("文")), ["Hans", "Hant"]);
+        assert!(query(FontPropertyBuilder::new().monospace()).is_empty());
+        assert!(query(FontPropertyBuilder::new().bold()).is_empty());
+        assert_eq!(query(FontPropertyBuilder::new().css_family("Latin, Hant")), ["Hant", "Latin"]);
+    }
+}
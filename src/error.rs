@@ -0,0 +1,97 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use libc::c_int;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::result;
+
+/// Errors returned while querying or loading fonts
+#[derive(Debug)]
+pub enum FontLoaderError {
+    /// No installed font matches the requested properties
+    NoMatch,
+    /// The matched font refers to a file which does not exist
+    MissingFile(PathBuf),
+    /// The backend returned a match without a required property, e.g. its file
+    MissingProperty(&'static str),
+    /// Reading the font file failed
+    Io(io::Error),
+    /// The family name cannot be passed to the backend, e.g. it contains a NUL byte
+    InvalidFamilyName(String),
+    /// The style name cannot be passed to the backend, e.g. it contains a NUL byte
+    InvalidStyleName(String),
+    /// The language is not a BCP 47 tag, e.g. "ja" or "zh-Hant"
+    InvalidLanguage(String),
+    /// The platform font backend could not be initialized
+    BackendInit,
+    /// The data is not a TrueType or OpenType font or collection
+    UnsupportedFormat,
+    /// A CSS `font` or `font-family` value can't be parsed
+    InvalidCss(String),
+    /// The font has no face with the index
+    InvalidFaceIndex(c_int),
+    /// The pixel size is not a positive, finite number
+    InvalidPixelSize(f32),
+}
+
+/// Result type used throughout this crate
+pub type Result<T> = result::Result<T, FontLoaderError>;
+
+impl fmt::Display for FontLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FontLoaderError::NoMatch => write!(f, "no font matches the requested properties"),
+            FontLoaderError::MissingFile(ref path) => {
+                write!(f, "font file {} does not exist", path.display())
+            }
+            FontLoaderError::MissingProperty(name) => {
+                write!(f, "matched font has no {} property", name)
+            }
+            FontLoaderError::Io(ref err) => write!(f, "failed to read font file: {}", err),
+            FontLoaderError::InvalidFamilyName(ref name) => {
+                write!(f, "invalid font family name {:?}", name)
+            }
+            FontLoaderError::InvalidStyleName(ref name) => write!(f, "invalid font style name {:?}", name),
+            FontLoaderError::InvalidLanguage(ref tag) => write!(f, "invalid language tag {:?}", tag),
+            FontLoaderError::BackendInit => write!(f, "failed to initialize the font backend"),
+            FontLoaderError::UnsupportedFormat => write!(f, "unsupported font format"),
+            FontLoaderError::InvalidCss(ref value) => write!(f, "invalid CSS font value {:?}", value),
+            FontLoaderError::InvalidFaceIndex(index) => write!(f, "font has no face with index {}", index),
+            FontLoaderError::InvalidPixelSize(size) => write!(f, "invalid pixel size {}", size),
+        }
+    }
+}
+
+impl Error for FontLoaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            FontLoaderError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FontLoaderError {
+    fn from(err: io::Error) -> FontLoaderError {
+        FontLoaderError::Io(err)
+    }
+}
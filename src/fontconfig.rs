@@ -18,193 +18,1088 @@
 
 /// Font loading utilities for installed system fonts
 pub mod system_fonts {
-    use servo_fontconfig::fontconfig::{FcConfig, FcInitLoadConfigAndFonts, FcNameParse};
+    use servo_fontconfig::fontconfig::{FcConfig, FcConfigUptoDate};
     use servo_fontconfig::fontconfig::{FcPattern, FcPatternCreate, FcPatternDestroy, FcFontMatch};
-    use servo_fontconfig::fontconfig::{FcFontList, FcObjectSetBuild, FcChar8, FcDefaultSubstitute};
+    use servo_fontconfig::fontconfig::{FcFontList, FcFontSet, FcChar8, FcDefaultSubstitute};
     use servo_fontconfig::fontconfig::{FcPatternGetString, FcPatternAddInteger, FcPatternGetInteger};
     use servo_fontconfig::fontconfig::{FcResultMatch, FcMatchPattern, FcResultNoMatch, FcConfigSubstitute};
-    use servo_fontconfig::fontconfig::FcPatternAddString;
+    use servo_fontconfig::fontconfig::{FcPatternAddString, FcPatternGetBool, FcBool};
+    use servo_fontconfig::fontconfig::{FcPatternAddBool, FcPatternAddDouble, FcPatternGetDouble};
+    use servo_fontconfig::fontconfig::{FcObjectSet, FcObjectSetCreate, FcObjectSetAdd};
+    use servo_fontconfig::fontconfig::{FcObjectSetDestroy, FcFontSetDestroy, FcFontSort};
+    use servo_fontconfig::fontconfig::{FcCharSet, FcCharSetCreate, FcCharSetAddChar, FcCharSetDestroy};
+    use servo_fontconfig::fontconfig::{FcCharSetHasChar, FcPatternAddCharSet, FcPatternGetCharSet};
+    use servo_fontconfig::fontconfig::{FcConfigAppFontAddFile, FcConfigAppFontAddDir};
+    use servo_fontconfig::fontconfig::{FcConfigCreate, FcConfigDestroy, FcConfigParseAndLoad};
+    use servo_fontconfig::fontconfig::{FcConfigBuildFonts, FcConfigEnableHome};
+    use servo_fontconfig::fontconfig::{FcConfigGetFontDirs, FcStrListNext, FcStrListDone};
 
-    use libc::{c_int, c_char};
+    use libc::{c_int, c_char, c_double};
 
+    use std::cmp;
+    use std::collections::{BTreeSet, HashSet};
     use std::ptr;
     use std::slice;
-    use std::ffi::{CStr, CString};
-    use std::io::prelude::*;
-    use std::fs::File;
-
-    use std::sync::{Once, ONCE_INIT};
-
-    static FC_FAMILY: &'static [u8] = b"family\0";
-    static FC_FILE: &'static [u8] = b"file\0";
-    static FC_WEIGHT: &'static [u8] = b"weight\0";
-    static FC_INDEX: &'static [u8] = b"index\0";
-    static FC_SLANT: &'static [u8] = b"slant\0";
-    static FC_SPACING: &'static [u8] = b"spacing\0";
-    //  static FC_FONTFORMAT: &'static [u8] = b"fontformat\0";
-    // 	static FC_STYLE: &'static [u8] = b"style\0";
-    // 	static FC_FAMILYLANG: &'static[u8] = b"familylang\0";
-    // 	static FC_CHARSET: &'static [u8] = b"charset\0";
-    // 	static FC_LANG: &'static [u8] = b"lang\0";
-    // 	static FC_STYLELANG: &'static [u8] = b"stylelang\0";
-
-    // 	static FC_WEIGHT_THIN: c_int 		= 0;
-    // 	static FC_WEIGHT_EXTRALIGHT: c_int 	= 40;
-    // 	static FC_WEIGHT_LIGHT: c_int 		= 50;
-    // 	static FC_WEIGHT_DEMILIGHT: c_int 	= 55;
-    // 	static FC_WEIGHT_BOOK: c_int 		= 75;
-    static FC_WEIGHT_REGULAR: c_int = 80;
-    // 	static FC_WEIGHT_MEDIUM: c_int 		= 100;
-    // 	static FC_WEIGHT_DEMIBOLD: c_int 	= 180;
-    static FC_WEIGHT_BOLD: c_int = 200;
-    // 	static FC_WEIGHT_EXTRABOLD: c_int 	= 205;
-    // 	static FC_WEIGHT_BLACK: c_int 		= 210;
-    // 	static FC_WEIGHT_EXTRA_BLACK: c_int = 215;
+    use std::ffi::{CStr, CString, OsStr};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use error::{FontLoaderError, Result};
+    use handle::Source;
+    use matching::locate_in_stack;
+    pub use handle::FontHandle;
+    pub use info::{FontInfo, StyleInfo};
+    pub use property::{Family, FontProperty, FontPropertyBuilder, GenericFamily};
+    pub use property::{FontFormat, Slant, Spacing, Stretch};
+
+    static FC_FAMILY: &[u8] = b"family\0";
+    static FC_FILE: &[u8] = b"file\0";
+    static FC_WEIGHT: &[u8] = b"weight\0";
+    static FC_INDEX: &[u8] = b"index\0";
+    static FC_SLANT: &[u8] = b"slant\0";
+    static FC_SPACING: &[u8] = b"spacing\0";
+    static FC_WIDTH: &[u8] = b"width\0";
+    static FC_FONTFORMAT: &[u8] = b"fontformat\0";
+    static FC_STYLE: &[u8] = b"style\0";
+    static FC_FULLNAME: &[u8] = b"fullname\0";
+    static FC_POSTSCRIPT_NAME: &[u8] = b"postscriptname\0";
+    static FC_SCALABLE: &[u8] = b"scalable\0";
+    static FC_PIXEL_SIZE: &[u8] = b"pixelsize\0";
+    // 	static FC_FAMILYLANG: &[u8] = b"familylang\0";
+    static FC_CHARSET: &[u8] = b"charset\0";
+    static FC_LANG: &[u8] = b"lang\0";
+    // 	static FC_STYLELANG: &[u8] = b"stylelang\0";
+
+    const FC_WEIGHT_THIN: c_int = 0;
+    const FC_WEIGHT_EXTRALIGHT: c_int = 40;
+    const FC_WEIGHT_LIGHT: c_int = 50;
+    const FC_WEIGHT_DEMILIGHT: c_int = 55;
+    const FC_WEIGHT_BOOK: c_int = 75;
+    const FC_WEIGHT_REGULAR: c_int = 80;
+    const FC_WEIGHT_MEDIUM: c_int = 100;
+    const FC_WEIGHT_DEMIBOLD: c_int = 180;
+    const FC_WEIGHT_BOLD: c_int = 200;
+    const FC_WEIGHT_EXTRABOLD: c_int = 205;
+    const FC_WEIGHT_BLACK: c_int = 210;
+    const FC_WEIGHT_EXTRA_BLACK: c_int = 215;
+
+    /// OpenType to fontconfig weight pairs, as used by `FcWeightFromOpenType`
+    static WEIGHT_MAP: [(c_int, c_int); 13] = [
+        (0, FC_WEIGHT_THIN),
+        (100, FC_WEIGHT_THIN),
+        (200, FC_WEIGHT_EXTRALIGHT),
+        (300, FC_WEIGHT_LIGHT),
+        (350, FC_WEIGHT_DEMILIGHT),
+        (380, FC_WEIGHT_BOOK),
+        (400, FC_WEIGHT_REGULAR),
+        (500, FC_WEIGHT_MEDIUM),
+        (600, FC_WEIGHT_DEMIBOLD),
+        (700, FC_WEIGHT_BOLD),
+        (800, FC_WEIGHT_EXTRABOLD),
+        (900, FC_WEIGHT_BLACK),
+        (1000, FC_WEIGHT_EXTRA_BLACK),
+    ];
 
     static FC_SLANT_ROMAN: c_int = 0;
     static FC_SLANT_ITALIC: c_int = 100;
     static FC_SLANT_OBLIQUE: c_int = 110;
 
-    //    static FC_PROPORTIONAL: c_int = 0;
-    // 	static FC_DUAL: c_int = 90;
+    static FC_PROPORTIONAL: c_int = 0;
+    static FC_DUAL: c_int = 90;
     static FC_MONO: c_int = 100;
-    // 	static FC_CHARCELL: c_int = 110;
+    static FC_CHARCELL: c_int = 110;
 
-    static INIT_FONTCONFIG: Once = ONCE_INIT;
-    static mut CONFIG: *mut FcConfig = 0 as *mut FcConfig;
+    /// The process-wide configuration used by the functions of this module, loaded on first use
+    static DEFAULT: Mutex<Option<Arc<Fontconfig>>> = Mutex::new(None);
 
-    fn init() -> *mut FcConfig {
-        unsafe {
-            INIT_FONTCONFIG.call_once(|| {
-                CONFIG = FcInitLoadConfigAndFonts();
-            });
-            CONFIG
+    /// Serializes building configurations, as disabling the user configuration is process-wide
+    static BUILD_LOCK: Mutex<()> = Mutex::new(());
+
+    extern "C" {
+        // Missing from the bindings, available since fontconfig 2.10.92
+        fn FcConfigSetSysRoot(config: *mut FcConfig, sysroot: *const FcChar8);
+        fn FcConfigGetSysRoot(config: *const FcConfig) -> *const FcChar8;
+    }
+
+    fn init() -> Result<Arc<Fontconfig>> {
+        let mut default = DEFAULT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match *default {
+            Some(ref fontconfig) => Ok(fontconfig.clone()),
+            None => {
+                let fontconfig = Arc::new(Fontconfig::new()?);
+                *default = Some(fontconfig.clone());
+                Ok(fontconfig)
+            }
         }
     }
 
-    /// The platform specific font properties
-    pub struct FontProperty {
-        slant: c_int,
-        weight: c_int,
-        family: String,
-        spacing: Option<c_int>,
+    /// Register a font file for this process, after which it is matched like an installed font.
+    /// Fails with `UnsupportedFormat` if fontconfig can't read it
+    pub fn add_font_file<P: AsRef<Path>>(path: P) -> Result<()> {
+        init()?.add_font_file(path)
     }
 
-    /// Builder for FontProperty
-    pub struct FontPropertyBuilder {
-        property: FontProperty,
+    /// Register the fonts in a directory and its subdirectories for this process
+    pub fn add_font_dir<P: AsRef<Path>>(path: P) -> Result<()> {
+        init()?.add_font_dir(path)
     }
 
-    impl FontPropertyBuilder {
-        pub fn new() -> FontPropertyBuilder {
-            let property = FontProperty {
-                slant: FC_SLANT_ROMAN,
-                weight: FC_WEIGHT_REGULAR,
-                family: String::new(),
-                spacing: None,
-            };
-            FontPropertyBuilder { property: property }
+    /// The font directories of the default configuration, for `FontWatcher`
+    #[cfg(feature = "watch")]
+    pub(crate) fn font_dirs() -> Result<Vec<PathBuf>> {
+        Ok(init()?.font_dirs())
+    }
+
+    /// Reload the installed fonts if font directories or the configuration changed since they
+    /// were loaded. Returns whether they were reloaded. Registered application fonts are kept
+    pub fn rescan() -> Result<bool> {
+        init()?.rescan()
+    }
+
+    /// Get the binary data and index of a specific font
+    /// Fonts of every format fontconfig knows are returned, `FontPropertyBuilder::formats`
+    /// restricts them
+    pub fn get(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        init()?.get(property)
+    }
+
+    /// Resolve the file and face index of a specific font without reading it
+    pub fn locate(property: &FontProperty) -> Result<FontHandle> {
+        init()?.locate(property)
+    }
+
+    /// Find the best installed face containing the character, preferring faces close to the
+    /// requested properties
+    pub fn fallback_for(c: char, property: &FontProperty) -> Result<FontHandle> {
+        init()?.fallback_for(c, property)
+    }
+
+    /// Get all faces ordered from best to worst match, as a fallback list for shaping text.
+    /// With `trim`, faces which don't contribute characters missing from the faces before them
+    /// are left out
+    pub fn match_sorted(property: &FontProperty, trim: bool) -> Result<Vec<FontHandle>> {
+        init()?.match_sorted(property, trim)
+    }
+
+    /// Query the names of all fonts installed in the system
+    pub fn query_all() -> Result<Vec<String>> {
+        init()?.query_all()
+    }
+
+    /// Query the names of specifc fonts installed in the system
+    /// Fonts of every format fontconfig knows are listed, `FontPropertyBuilder::formats`
+    /// restricts them
+    pub fn query_specific(property: &FontProperty) -> Result<Vec<String>> {
+        init()?.query_specific(property)
+    }
+
+    /// Query every face of the fonts matching the property's families and spacing.
+    /// Weight, slant and stretch are ignored, so that all styles of a family are returned
+    pub fn query_faces(property: &FontProperty) -> Result<Vec<FontInfo>> {
+        init()?.query_faces(property)
+    }
+
+    /// The styles the installed family provides, e.g. to let users pick one by name for
+    /// `FontPropertyBuilder::style`
+    pub fn styles_of(family: &str) -> Result<Vec<StyleInfo>> {
+        init()?.styles_of(family)
+    }
+
+    /// Get the binary data and index of the first family of the property which is installed,
+    /// as CSS walks a font stack. Unlike `get`, no other family is substituted, so this fails
+    /// with `NoMatch` if none of them is installed
+    pub fn get_first_available(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        init()?.get_first_available(property)
+    }
+
+    /// Resolve the file and face index of the face CSS Fonts Level 4 font matching selects,
+    /// among the faces of the first family of the property which is installed. Unlike
+    /// `locate`, the result doesn't depend on the platform
+    pub fn locate_css(property: &FontProperty) -> Result<FontHandle> {
+        init()?.locate_css(property)
+    }
+
+    /// Get the binary data and index of the installed face with the PostScript name, such as
+    /// `Roboto-BoldItalic`, as documents reference fonts. No other face is substituted, names
+    /// are compared ignoring case
+    pub fn get_by_postscript_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        init()?.get_by_postscript_name(name)
+    }
+
+    /// Get the binary data and index of the installed face with the full name, such as
+    /// `Roboto Bold Italic`, in any of its languages. No other face is substituted, names are
+    /// compared ignoring case
+    pub fn get_by_full_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        init()?.get_by_full_name(name)
+    }
+
+    /// Resolve a generic family to the installed families fontconfig is configured to use for
+    /// it, most preferred first
+    pub fn resolve_generic(generic: GenericFamily) -> Vec<String> {
+        init().map(|fontconfig| fontconfig.resolve_generic(generic)).unwrap_or_default()
+    }
+
+    /// A fontconfig configuration owned by the caller, independent of the process-wide
+    /// configuration used by the functions of this module
+    ///
+    /// Contexts can be shared between threads. Calls on one context are serialized, so threads
+    /// which query fonts concurrently are better served by a context each.
+    ///
+    /// ```no_run
+    /// # use font_loader::system_fonts::Fontconfig;
+    /// let fonts = Fontconfig::builder()
+    ///     .config_file("/etc/fonts/fonts.conf")
+    ///     .no_user_config()
+    ///     .build()
+    ///     .unwrap();
+    /// let families = fonts.query_all().unwrap();
+    /// ```
+    pub struct Fontconfig {
+        state: Mutex<State>,
+    }
+
+    /// A loaded configuration and what is needed to load it again
+    struct State {
+        config: Config,
+        builder: FontconfigBuilder,
+        app_fonts: Vec<(PathBuf, AddFont)>,
+    }
+
+    /// `FcConfigAppFontAddFile` or `FcConfigAppFontAddDir`
+    type AddFont = unsafe extern "C" fn(*mut FcConfig, *const FcChar8) -> FcBool;
+
+    /// Alias of `Fontconfig`
+    pub type FontconfigBackend = Fontconfig;
+
+    impl Fontconfig {
+        /// Loads the default configuration and the fonts it lists
+        pub fn new() -> Result<Fontconfig> {
+            FontconfigBuilder::new().build()
         }
 
-        pub fn italic(mut self) -> FontPropertyBuilder {
-            self.property.slant = FC_SLANT_ITALIC;
-            self
+        pub fn builder() -> FontconfigBuilder {
+            FontconfigBuilder::new()
         }
 
-        pub fn oblique(mut self) -> FontPropertyBuilder {
-            self.property.slant = FC_SLANT_OBLIQUE;
-            self
+        fn lock(&self) -> MutexGuard<'_, State> {
+            self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+
+        /// Register a font file with this configuration only
+        pub fn add_font_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+            self.add_app_font(path.as_ref(), FcConfigAppFontAddFile)
+        }
+
+        /// Register the fonts in a directory and its subdirectories with this configuration only
+        pub fn add_font_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+            self.add_app_font(path.as_ref(), FcConfigAppFontAddDir)
+        }
+
+        fn add_app_font(&self, path: &Path, add: AddFont) -> Result<()> {
+            let mut state = self.lock();
+            state.config.add_app_font(path, add)?;
+            state.app_fonts.push((path.to_path_buf(), add));
+            Ok(())
+        }
+
+        /// See `system_fonts::rescan`
+        pub fn rescan(&self) -> Result<bool> {
+            let mut state = self.lock();
+            if unsafe { FcConfigUptoDate(state.config.0) } != 0 {
+                return Ok(false);
+            }
+            let config = state.builder.load()?;
+            for &(ref path, add) in &state.app_fonts {
+                // Fonts removed in the meantime are dropped
+                let _ = config.add_app_font(path, add);
+            }
+            state.config = config;
+            Ok(true)
+        }
+
+        /// The directories the configuration scans for fonts, including their subdirectories.
+        /// Directories which don't exist are listed as well
+        pub fn font_dirs(&self) -> Vec<PathBuf> {
+            self.lock().config.font_dirs()
+        }
+
+        /// See `system_fonts::get`
+        pub fn get(&self, property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+            let handle = self.locate(property)?;
+            Ok((handle.load()?, handle.index()))
+        }
+
+        /// See `system_fonts::locate`
+        pub fn locate(&self, property: &FontProperty) -> Result<FontHandle> {
+            self.lock().config.locate(property)
+        }
+
+        /// See `system_fonts::fallback_for`
+        pub fn fallback_for(&self, c: char, property: &FontProperty) -> Result<FontHandle> {
+            self.lock().config.fallback_for(c, property)
+        }
+
+        /// See `system_fonts::match_sorted`
+        pub fn match_sorted(&self, property: &FontProperty, trim: bool) -> Result<Vec<FontHandle>> {
+            self.lock().config.match_sorted(property, trim)
+        }
+
+        /// See `system_fonts::query_all`
+        pub fn query_all(&self) -> Result<Vec<String>> {
+            let property = FontPropertyBuilder::new().build()?;
+            self.query_specific(&property)
+        }
+
+        /// See `system_fonts::query_specific`
+        pub fn query_specific(&self, property: &FontProperty) -> Result<Vec<String>> {
+            self.lock().config.query_specific(property)
         }
 
-        pub fn bold(mut self) -> FontPropertyBuilder {
-            self.property.weight = FC_WEIGHT_BOLD;
+        /// See `system_fonts::query_faces`
+        pub fn query_faces(&self, property: &FontProperty) -> Result<Vec<FontInfo>> {
+            self.lock().config.query_faces(property)
+        }
+
+        /// See `system_fonts::styles_of`
+        pub fn styles_of(&self, family: &str) -> Result<Vec<StyleInfo>> {
+            let property = FontPropertyBuilder::new().family(family).build()?;
+            Ok(StyleInfo::of_faces(&self.query_faces(&property)?))
+        }
+
+        /// See `system_fonts::get_first_available`
+        pub fn get_first_available(&self, property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+            if property.families().is_empty() {
+                return self.get(property);
+            }
+            for single in property.family_stack(|generic| self.resolve_generic(generic)) {
+                if !self.query_faces(&single)?.is_empty() {
+                    return self.get(&single);
+                }
+            }
+            Err(FontLoaderError::NoMatch)
+        }
+
+        /// See `system_fonts::locate_css`
+        pub fn locate_css(&self, property: &FontProperty) -> Result<FontHandle> {
+            locate_in_stack(property, |generic| self.resolve_generic(generic), || self.locate(property),
+                            |single| self.query_faces(single))
+        }
+
+        /// See `system_fonts::get_by_postscript_name`
+        pub fn get_by_postscript_name(&self, name: &str) -> Result<(Vec<u8>, c_int)> {
+            let handle = self.lock().config.locate_by_name(FC_POSTSCRIPT_NAME, name)?;
+            Ok((handle.load()?, handle.index()))
+        }
+
+        /// See `system_fonts::get_by_full_name`
+        pub fn get_by_full_name(&self, name: &str) -> Result<(Vec<u8>, c_int)> {
+            let handle = self.lock().config.locate_by_name(FC_FULLNAME, name)?;
+            Ok((handle.load()?, handle.index()))
+        }
+
+        /// See `system_fonts::resolve_generic`
+        pub fn resolve_generic(&self, generic: GenericFamily) -> Vec<String> {
+            self.lock().config.resolve_generic(generic)
+        }
+    }
+
+    /// Builder for Fontconfig
+    #[derive(Clone, Debug, Default)]
+    pub struct FontconfigBuilder {
+        config_file: Option<PathBuf>,
+        sysroot: Option<PathBuf>,
+        no_user_config: bool,
+    }
+
+    impl FontconfigBuilder {
+        pub fn new() -> FontconfigBuilder {
+            FontconfigBuilder::default()
+        }
+
+        /// Loads the configuration file instead of the default fonts.conf, usually found in
+        /// /etc/fonts. Absolute paths are looked up inside the sysroot, if one is set
+        pub fn config_file<P: AsRef<Path>>(mut self, path: P) -> FontconfigBuilder {
+            self.config_file = Some(path.as_ref().to_path_buf());
             self
         }
 
-        pub fn monospace(mut self) -> FontPropertyBuilder {
-            self.property.spacing = Some(FC_MONO);
+        /// Resolves the paths of the configuration, like its font and cache directories, inside
+        /// the directory, as when rendering with the fonts of another system image
+        pub fn sysroot<P: AsRef<Path>>(mut self, path: P) -> FontconfigBuilder {
+            self.sysroot = Some(path.as_ref().to_path_buf());
             self
         }
 
-        pub fn family(mut self, name: &str) -> FontPropertyBuilder {
-            self.property.family.clear();
-            self.property.family.push_str(name);
+        /// Ignores configuration and fonts in the user's home directory, like
+        /// ~/.config/fontconfig and ~/.local/share/fonts
+        pub fn no_user_config(mut self) -> FontconfigBuilder {
+            self.no_user_config = true;
             self
         }
 
-        pub fn build(self) -> FontProperty {
-            self.property
+        /// Parses the configuration and scans the fonts it lists. Fails with `MissingFile` if
+        /// the configuration file doesn't exist and with `BackendInit` if it can't be loaded
+        pub fn build(self) -> Result<Fontconfig> {
+            let config = self.load()?;
+            let state = State { config, builder: self, app_fonts: Vec::new() };
+            Ok(Fontconfig { state: Mutex::new(state) })
+        }
+
+        fn load(&self) -> Result<Config> {
+            let config_file = match self.config_file {
+                Some(ref file) => {
+                    check_exists(&self.resolve(file))?;
+                    Some(path_cstring(file)?)
+                }
+                None => None,
+            };
+            let sysroot = match self.sysroot {
+                Some(ref sysroot) => {
+                    check_exists(sysroot)?;
+                    Some(path_cstring(sysroot)?)
+                }
+                None => None,
+            };
+
+            let _guard = BUILD_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let config = Config::new(unsafe { FcConfigCreate() })?;
+            unsafe {
+                if let Some(ref sysroot) = sysroot {
+                    FcConfigSetSysRoot(config.0, sysroot.as_ptr() as *const FcChar8);
+                }
+                let file = config_file.as_ref()
+                    .map_or(ptr::null(), |file| file.as_ptr() as *const FcChar8);
+                let home_enabled = FcConfigEnableHome(!self.no_user_config as FcBool);
+                let loaded = FcConfigParseAndLoad(config.0, file, 1) != 0 &&
+                             FcConfigBuildFonts(config.0) != 0;
+                FcConfigEnableHome(home_enabled);
+                if !loaded {
+                    return Err(FontLoaderError::BackendInit);
+                }
+            }
+            Ok(config)
+        }
+
+        /// Path of a configuration file as fontconfig opens it, inside the sysroot
+        fn resolve(&self, file: &Path) -> PathBuf {
+            match self.sysroot {
+                Some(ref sysroot) if file.is_absolute() && !file.starts_with(sysroot) => {
+                    sysroot.join(file.strip_prefix("/").unwrap_or(file))
+                }
+                _ => file.to_path_buf(),
+            }
         }
     }
 
-    /// Get the binary data and index of a specific font
-    /// Note that only truetype fonts are supported
-    pub fn get(property: &FontProperty) -> Option<(Vec<u8>, c_int)> {
-        let config = init();
-        let family: &str = &property.family;
+    /// Owns an `FcConfig`, destroyed on drop
+    struct Config(*mut FcConfig);
 
-        unsafe {
-            let name = CString::new(family).unwrap();
-            let pat = FcNameParse(name.as_ptr() as *const FcChar8);
-            add_int(pat, FC_SLANT, property.slant);
-            add_int(pat, FC_WEIGHT, property.weight);
-            FcConfigSubstitute(config, pat, FcMatchPattern);
-            FcDefaultSubstitute(pat);
+    // Configurations aren't bound to the thread creating them, `Fontconfig` serializes access
+    unsafe impl Send for Config {}
+
+    impl Drop for Config {
+        fn drop(&mut self) {
+            unsafe { FcConfigDestroy(self.0) };
+        }
+    }
+
+    impl Config {
+        fn new(config: *mut FcConfig) -> Result<Config> {
+            if config.is_null() {
+                Err(FontLoaderError::BackendInit)
+            } else {
+                Ok(Config(config))
+            }
+        }
 
+        /// The sysroot the configuration resolves paths in, if any
+        fn sysroot(&self) -> Option<PathBuf> {
+            unsafe {
+                let sysroot = FcConfigGetSysRoot(self.0);
+                if sysroot.is_null() {
+                    None
+                } else {
+                    let bytes = CStr::from_ptr(sysroot as *const c_char).to_bytes();
+                    Some(PathBuf::from(OsStr::from_bytes(bytes)))
+                }
+            }
+        }
+
+        fn font_dirs(&self) -> Vec<PathBuf> {
+            let sysroot = self.sysroot();
+            let mut dirs = Vec::new();
+            unsafe {
+                let list = FcConfigGetFontDirs(self.0);
+                if list.is_null() {
+                    return dirs;
+                }
+                loop {
+                    let dir = FcStrListNext(list);
+                    if dir.is_null() {
+                        break;
+                    }
+                    let bytes = CStr::from_ptr(dir as *const c_char).to_bytes();
+                    dirs.push(in_sysroot(PathBuf::from(OsStr::from_bytes(bytes)), sysroot.as_deref()));
+                }
+                FcStrListDone(list);
+            }
+            dirs
+        }
+
+        fn add_app_font(&self, path: &Path, add: AddFont) -> Result<()> {
+            check_exists(path)?;
+            let path_str = path_cstring(path)?;
+            if unsafe { add(self.0, path_str.as_ptr() as *const FcChar8) } == 0 {
+                return Err(FontLoaderError::UnsupportedFormat);
+            }
+            Ok(())
+        }
+
+        /// The pattern matching the property, with the configuration's substitutions applied.
+        /// Only matching prefers strikes close to the pixel size, listing would require it exactly
+        fn match_pattern(&self, property: &FontProperty) -> Result<Pattern> {
+            let pattern = to_pattern(property)?;
+            if let Some(size) = property.pixel_size() {
+                add_double(pattern.0, FC_PIXEL_SIZE, c_double::from(size));
+            }
+            unsafe {
+                FcConfigSubstitute(self.0, pattern.0, FcMatchPattern);
+                FcDefaultSubstitute(pattern.0);
+            }
+            Ok(pattern)
+        }
+
+        fn locate(&self, property: &FontProperty) -> Result<FontHandle> {
+            // The format, scalability and characters aren't hard constraints of matching, so
+            // the best allowed font is looked up in the sorted fonts instead
+            if !property.formats().is_empty() || property.scalable().is_some() || !property.chars().is_empty() {
+                let sysroot = self.sysroot();
+                let mut handle = Err(FontLoaderError::NoMatch);
+                self.font_sort(property, false, |pat| {
+                    if !has_chars(pat, property.chars().iter().cloned()) {
+                        return true;
+                    }
+                    handle = to_handle(pat, sysroot.as_deref());
+                    false
+                })?;
+                return handle;
+            }
+            let pattern = self.match_pattern(property)?;
+            let mut result = FcResultNoMatch;
+            let font = Pattern::new(unsafe { FcFontMatch(self.0, pattern.0, &mut result) })
+                .ok_or(FontLoaderError::NoMatch)?;
+            to_handle(font.0, self.sysroot().as_deref())
+        }
+
+        fn fallback_for(&self, c: char, property: &FontProperty) -> Result<FontHandle> {
+            let mut property = property.clone();
+            property.chars.insert(c);
+
+            let sysroot = self.sysroot();
+            let mut handle = Err(FontLoaderError::NoMatch);
+            self.font_sort(&property, true, |pat| {
+                if has_chars(pat, Some(c)) {
+                    handle = to_handle(pat, sysroot.as_deref());
+                    false
+                } else {
+                    true
+                }
+            })?;
+            handle
+        }
+
+        fn match_sorted(&self, property: &FontProperty, trim: bool) -> Result<Vec<FontHandle>> {
+            let sysroot = self.sysroot();
+            let mut handles = Vec::new();
+            self.font_sort(property, trim, |pat| {
+                if let Ok(handle) = to_handle(pat, sysroot.as_deref()) {
+                    handles.push(handle);
+                }
+                true
+            })?;
+            Ok(handles)
+        }
+
+        /// Runs `FcFontSort` and visits the sorted fonts in order until `visit` returns false.
+        /// Fonts the property doesn't allow are skipped
+        fn font_sort<F>(&self, property: &FontProperty, trim: bool, mut visit: F) -> Result<()>
+            where F: FnMut(*mut FcPattern) -> bool
+        {
+            let pattern = self.match_pattern(property)?;
             let mut result = FcResultNoMatch;
-            let font_pat = FcFontMatch(config, pat, &mut result);
+            let fs = unsafe {
+                FcFontSort(self.0, pattern.0, trim as FcBool, ptr::null_mut(), &mut result)
+            };
+            let fs = FontSet::new(fs).ok_or(FontLoaderError::NoMatch)?;
+            let sysroot = self.sysroot();
+            for &pat in fs.patterns() {
+                if !is_allowed(pat, property, sysroot.as_deref()) {
+                    continue;
+                }
+                if !visit(pat) {
+                    break;
+                }
+            }
+            Ok(())
+        }
+
+        fn query_specific(&self, property: &FontProperty) -> Result<Vec<String>> {
+            let mut fonts = Vec::new();
+            self.font_list(property, to_pattern, &[FC_FAMILY], |pat| {
+                if let Ok(family) = get_string(pat, FC_FAMILY) {
+                    fonts.push(family);
+                }
+            })?;
+            fonts.sort();
+            fonts.dedup();
+            Ok(fonts)
+        }
+
+        fn query_faces(&self, property: &FontProperty) -> Result<Vec<FontInfo>> {
+            let objects = [FC_FAMILY, FC_STYLE, FC_FULLNAME, FC_POSTSCRIPT_NAME, FC_WEIGHT, FC_SLANT,
+                           FC_WIDTH, FC_SPACING, FC_FILE, FC_INDEX, FC_FONTFORMAT, FC_SCALABLE,
+                           FC_PIXEL_SIZE];
+            let sysroot = self.sysroot();
+            let mut faces = Vec::new();
+            self.font_list(property, to_list_pattern, &objects, |pat| {
+                if let Ok(info) = to_info(pat, sysroot.as_deref()) {
+                    faces.push(info);
+                }
+            })?;
+            faces.sort_by(|a, b| {
+                (&a.family, a.stretch, a.weight, a.slant, a.path(), a.index)
+                    .cmp(&(&b.family, b.stretch, b.weight, b.slant, b.path(), b.index))
+            });
+            // Faces listed for several of the requested families
+            faces.dedup_by(|a, b| a.path() == b.path() && a.index == b.index);
+            Ok(faces)
+        }
+
+        /// Runs `FcFontList` once per requested family and visits the listed fonts. A pattern
+        /// with several families only lists fonts having all of them, while the families of a
+        /// property are alternatives. Listing doesn't apply the configuration's aliases either,
+        /// so generic families are resolved first. Fonts the property doesn't allow are skipped
+        fn font_list<F>(&self, property: &FontProperty, to_pattern: fn(&FontProperty) -> Result<Pattern>,
+                        objects: &[&[u8]], mut visit: F) -> Result<()>
+            where F: FnMut(*mut FcPattern)
+        {
+            let singles = if property.families.len() <= 1 {
+                vec![property.resolve_generics(|generic| self.resolve_generic(generic))]
+            } else {
+                property.family_stack(|generic| self.resolve_generic(generic))
+            };
+            let mut objects = objects.to_vec();
+            if !property.formats().is_empty() {
+                objects.extend_from_slice(&[FC_FILE, FC_FONTFORMAT]);
+            }
+            if property.scalable().is_some() {
+                objects.push(FC_SCALABLE);
+            }
+            let os = ObjectSet::new(&objects)?;
+            let sysroot = self.sysroot();
+            for single in &singles {
+                let pattern = to_pattern(single)?;
+                if let Some(fs) = FontSet::new(unsafe { FcFontList(self.0, pattern.0, os.0) }) {
+                    for &pat in fs.patterns() {
+                        if is_allowed(pat, property, sysroot.as_deref()) {
+                            visit(pat);
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        /// The first listed face having the name among the values of the object
+        fn locate_by_name(&self, object_name: &[u8], name: &str) -> Result<FontHandle> {
+            let pattern = Pattern::new(unsafe { FcPatternCreate() }).ok_or(FontLoaderError::NoMatch)?;
+            add_string(pattern.0, object_name, name)?;
+            let os = ObjectSet::new(&[FC_FAMILY, FC_STYLE, FC_FILE, FC_INDEX, FC_SCALABLE, FC_PIXEL_SIZE])?;
+            let fs = FontSet::new(unsafe { FcFontList(self.0, pattern.0, os.0) })
+                .ok_or(FontLoaderError::NoMatch)?;
+            let sysroot = self.sysroot();
+            fs.patterns()
+                .iter()
+                .filter_map(|&pat| to_handle(pat, sysroot.as_deref()).ok())
+                .min_by(|a, b| (a.path(), a.index()).cmp(&(b.path(), b.index())))
+                .ok_or(FontLoaderError::NoMatch)
+        }
+
+        /// The families the configuration substitutes for the generic family, keeping the
+        /// installed ones
+        fn resolve_generic(&self, generic: GenericFamily) -> Vec<String> {
+            let pattern = match Pattern::new(unsafe { FcPatternCreate() }) {
+                Some(pattern) => pattern,
+                None => return Vec::new(),
+            };
+            if add_string(pattern.0, FC_FAMILY, generic.keyword()).is_err() {
+                return Vec::new();
+            }
+            unsafe { FcConfigSubstitute(self.0, pattern.0, FcMatchPattern) };
+
+            let installed = self.installed_families();
+            let mut families: Vec<String> = Vec::new();
+            for family in get_strings(pattern.0, FC_FAMILY) {
+                if installed.contains(&family.to_lowercase()) && !families.contains(&family) {
+                    families.push(family);
+                }
+            }
+            families
+        }
+
+        /// Lowercased names of the installed families, including their localized names
+        fn installed_families(&self) -> HashSet<String> {
+            let mut families = HashSet::new();
+            let pattern = match Pattern::new(unsafe { FcPatternCreate() }) {
+                Some(pattern) => pattern,
+                None => return families,
+            };
+            let os = match ObjectSet::new(&[FC_FAMILY]) {
+                Ok(os) => os,
+                Err(_) => return families,
+            };
+            if let Some(fs) = FontSet::new(unsafe { FcFontList(self.0, pattern.0, os.0) }) {
+                for &pat in fs.patterns() {
+                    families.extend(get_strings(pat, FC_FAMILY).iter().map(|family| family.to_lowercase()));
+                }
+            }
+            families
+        }
+    }
 
-            if font_pat.is_null() {
+    /// Owns an `FcPattern`, destroyed on drop
+    struct Pattern(*mut FcPattern);
+
+    impl Pattern {
+        fn new(pattern: *mut FcPattern) -> Option<Pattern> {
+            if pattern.is_null() {
                 None
             } else {
-                let file = get_string(font_pat, FC_FILE).unwrap();
-                let index = get_int(font_pat, FC_INDEX).unwrap();
-                FcPatternDestroy(font_pat);
-                let mut file = File::open(file).unwrap();
-                let mut buf: Vec<u8> = Vec::new();
-                let _ = file.read_to_end(&mut buf);
-                Some((buf, index))
+                Some(Pattern(pattern))
             }
         }
     }
 
-    /// Query the names of all fonts installed in the system
-    /// Note that only truetype fonts are supported
-    pub fn query_all() -> Vec<String> {
-        let mut property = FontPropertyBuilder::new().build();
-        query_specific(&mut property)
+    impl Drop for Pattern {
+        fn drop(&mut self) {
+            unsafe { FcPatternDestroy(self.0) };
+        }
     }
 
-    /// Query the names of specifc fonts installed in the system
-    /// Note that only truetype fonts are supported
-    pub fn query_specific(property: &mut FontProperty) -> Vec<String> {
-        let mut fonts: Vec<String> = Vec::new();
-        unsafe {
-            let config = init();
+    /// Owns an `FcFontSet` and the patterns in it, destroyed on drop
+    struct FontSet(*mut FcFontSet);
+
+    impl FontSet {
+        fn new(fs: *mut FcFontSet) -> Option<FontSet> {
+            if fs.is_null() {
+                None
+            } else {
+                Some(FontSet(fs))
+            }
+        }
+
+        fn patterns(&self) -> &[*mut FcPattern] {
+            unsafe {
+                if (*self.0).nfont > 0 {
+                    slice::from_raw_parts((*self.0).fonts, (*self.0).nfont as usize)
+                } else {
+                    &[]
+                }
+            }
+        }
+    }
+
+    impl Drop for FontSet {
+        fn drop(&mut self) {
+            unsafe { FcFontSetDestroy(self.0) };
+        }
+    }
+
+    /// Owns an `FcObjectSet`, destroyed on drop
+    struct ObjectSet(*mut FcObjectSet);
+
+    impl ObjectSet {
+        fn new(objects: &[&[u8]]) -> Result<ObjectSet> {
+            let os = unsafe { FcObjectSetCreate() };
+            if os.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            let os = ObjectSet(os);
+            for object in objects {
+                unsafe { FcObjectSetAdd(os.0, object.as_ptr() as *const c_char) };
+            }
+            Ok(os)
+        }
+    }
+
+    impl Drop for ObjectSet {
+        fn drop(&mut self) {
+            unsafe { FcObjectSetDestroy(self.0) };
+        }
+    }
+
+    fn check_exists(path: &Path) -> Result<()> {
+        match fs::metadata(path) {
+            Ok(_) => Ok(()),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {
+                Err(FontLoaderError::MissingFile(path.to_path_buf()))
+            }
+            Err(err) => Err(FontLoaderError::Io(err)),
+        }
+    }
+
+    /// A path for fontconfig. Paths containing NUL can't exist and are reported as missing
+    fn path_cstring(path: &Path) -> Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| FontLoaderError::MissingFile(path.to_path_buf()))
+    }
+
+    /// Translates the property into a fontconfig pattern
+    fn to_pattern(property: &FontProperty) -> Result<Pattern> {
+        let pattern = to_list_pattern(property)?;
+        add_int(pattern.0, FC_WEIGHT, fc_weight(property.weight()));
+        add_int(pattern.0, FC_SLANT, fc_slant(property.slant()));
+        // Listing requires an exact width, which would drop condensed only families when no
+        // stretch was asked for. Matching defaults to normal width anyway
+        if property.stretch() != Stretch::Normal {
+            add_int(pattern.0, FC_WIDTH, c_int::from(property.stretch().percentage()));
+        }
+        if let Some(style) = property.style() {
+            add_string(pattern.0, FC_STYLE, style)?;
+        }
+        Ok(pattern)
+    }
+
+    /// Translates the properties used for listing faces into a pattern
+    fn to_list_pattern(property: &FontProperty) -> Result<Pattern> {
+        let pattern = Pattern::new(unsafe { FcPatternCreate() }).ok_or(FontLoaderError::BackendInit)?;
+        // Generic families are passed by their keyword, which fontconfig's aliases resolve
+        for family in property.families() {
+            add_string(pattern.0, FC_FAMILY, family.name())?;
+        }
+        if let Some(spacing) = property.spacing() {
+            add_int(pattern.0, FC_SPACING, fc_spacing(spacing));
+        }
+        if let Some(scalable) = property.scalable() {
+            add_bool(pattern.0, FC_SCALABLE, scalable);
+        }
+        if !property.chars().is_empty() {
+            add_charset(pattern.0, property.chars());
+        }
+        if let Some(language) = property.language() {
+            add_string(pattern.0, FC_LANG, &fc_lang(language))?;
+        }
+        Ok(pattern)
+    }
+
+    /// Translates a BCP 47 tag into fontconfig's RFC 3066 style language, e.g. "zh-Hant" into
+    /// "zh-tw". Script subtags are dropped, as fontconfig distinguishes Chinese by region only
+    fn fc_lang(tag: &str) -> String {
+        let tag = tag.to_ascii_lowercase().replace('_', "-");
+        let mut subtags = tag.split('-');
+        let primary = subtags.next().unwrap_or("");
+        let mut script = None;
+        let mut region = None;
+        for subtag in subtags {
+            // Extensions and private use subtags follow a singleton, e.g. "ja-u-ca-japanese"
+            if subtag.len() == 1 {
+                break;
+            }
+            if subtag.len() == 4 && script.is_none() && region.is_none() {
+                script = Some(subtag);
+            } else if (subtag.len() == 2 || subtag.len() == 3) && region.is_none() {
+                region = Some(subtag);
+            }
+        }
+        if primary == "zh" {
+            let region = match (script, region) {
+                (_, Some(region)) if ["tw", "hk", "mo", "sg", "cn"].contains(&region) => region,
+                (Some("hant"), _) => "tw",
+                _ => "cn",
+            };
+            return format!("zh-{}", region);
+        }
+        match region {
+            Some(region) => format!("{}-{}", primary, region),
+            None => primary.to_string(),
+        }
+    }
+
+    /// Maps an OpenType weight onto fontconfig's scale, interpolating linearly between the
+    /// named weights like `FcWeightFromOpenType` does
+    fn fc_weight(weight: u16) -> c_int {
+        let weight = cmp::min(c_int::from(weight), 1000);
+        let mut i = 1;
+        while weight > WEIGHT_MAP[i].0 {
+            i += 1;
+        }
+        let (ot_high, fc_high) = WEIGHT_MAP[i];
+        if weight == ot_high {
+            return fc_high;
+        }
+        let (ot_low, fc_low) = WEIGHT_MAP[i - 1];
+        let fc = f64::from(fc_low)
+            + f64::from(weight - ot_low) * f64::from(fc_high - fc_low) / f64::from(ot_high - ot_low);
+        (fc + 0.5) as c_int
+    }
+
+    /// Maps a fontconfig weight back onto the OpenType scale, like `FcWeightToOpenType`
+    fn ot_weight(weight: c_int) -> u16 {
+        let weight = weight.clamp(0, FC_WEIGHT_EXTRA_BLACK);
+        let mut i = 1;
+        while weight > WEIGHT_MAP[i].1 {
+            i += 1;
+        }
+        let (ot_high, fc_high) = WEIGHT_MAP[i];
+        if weight == fc_high {
+            return ot_high as u16;
+        }
+        let (ot_low, fc_low) = WEIGHT_MAP[i - 1];
+        let ot = f64::from(ot_low)
+            + f64::from(weight - fc_low) * f64::from(ot_high - ot_low) / f64::from(fc_high - fc_low);
+        (ot + 0.5) as u16
+    }
+
+    fn fc_slant(slant: Slant) -> c_int {
+        match slant {
+            Slant::Roman => FC_SLANT_ROMAN,
+            Slant::Italic => FC_SLANT_ITALIC,
+            Slant::Oblique => FC_SLANT_OBLIQUE,
+        }
+    }
+
+    fn slant_from_fc(slant: c_int) -> Slant {
+        if slant < (FC_SLANT_ROMAN + FC_SLANT_ITALIC) / 2 {
+            Slant::Roman
+        } else if slant < (FC_SLANT_ITALIC + FC_SLANT_OBLIQUE) / 2 {
+            Slant::Italic
+        } else {
+            Slant::Oblique
+        }
+    }
+
+    fn spacing_from_fc(spacing: c_int) -> Spacing {
+        if spacing >= FC_CHARCELL {
+            Spacing::CharCell
+        } else if spacing >= FC_MONO {
+            Spacing::Monospace
+        } else if spacing >= FC_DUAL {
+            Spacing::Dual
+        } else {
+            Spacing::Proportional
+        }
+    }
+
+    fn fc_spacing(spacing: Spacing) -> c_int {
+        match spacing {
+            Spacing::Proportional => FC_PROPORTIONAL,
+            Spacing::Dual => FC_DUAL,
+            Spacing::Monospace => FC_MONO,
+            Spacing::CharCell => FC_CHARCELL,
+        }
+    }
+
+    fn to_handle(pat: *mut FcPattern, sysroot: Option<&Path>) -> Result<FontHandle> {
+        let file = file_path(pat, sysroot)?;
+        let index = get_int(pat, FC_INDEX)?;
+        let family = get_string(pat, FC_FAMILY).unwrap_or_default();
+        let style = get_string(pat, FC_STYLE).unwrap_or_default();
+        Ok(FontHandle::new(Source::File(file), index, family, style).with_pixel_size(strike_size(pat)))
+    }
+
+    fn to_info(pat: *mut FcPattern, sysroot: Option<&Path>) -> Result<FontInfo> {
+        let width = get_int(pat, FC_WIDTH).unwrap_or(100);
+        Ok(FontInfo {
+            family: get_string(pat, FC_FAMILY).unwrap_or_default(),
+            style: get_string(pat, FC_STYLE).unwrap_or_default(),
+            full_name: get_string(pat, FC_FULLNAME).unwrap_or_default(),
+            postscript_name: get_string(pat, FC_POSTSCRIPT_NAME).unwrap_or_default(),
+            weight: ot_weight(get_int(pat, FC_WEIGHT).unwrap_or(FC_WEIGHT_REGULAR)),
+            slant: slant_from_fc(get_int(pat, FC_SLANT).unwrap_or(FC_SLANT_ROMAN)),
+            stretch: Stretch::from_percentage(cmp::max(0, width) as u16),
+            spacing: spacing_from_fc(get_int(pat, FC_SPACING).unwrap_or(FC_PROPORTIONAL)),
+            source: Source::File(file_path(pat, sysroot)?),
+            index: get_int(pat, FC_INDEX)?,
+            format: font_format(pat, sysroot),
+            scalable: get_bool(pat, FC_SCALABLE).unwrap_or(true),
+            pixel_size: strike_size(pat).map(f32::to_bits),
+        })
+    }
 
-            let pattern = FcPatternCreate();
-            if !property.family.is_empty() {
-                add_string(pattern, FC_FAMILY, &property.family);
+    /// Pixel size of a bitmap font's strike. A matched scalable font has the requested size
+    /// instead, which isn't reported
+    fn strike_size(pat: *mut FcPattern) -> Option<f32> {
+        if get_bool(pat, FC_SCALABLE).unwrap_or(true) {
+            return None;
+        }
+        get_double(pat, FC_PIXEL_SIZE).ok().map(|size| size as f32)
+    }
+
+    /// Format of the font by fontconfig's name for it. FreeType reads WOFF and WOFF2 files as
+    /// the font they wrap, so these are told apart by the file's magic bytes
+    fn font_format(pat: *mut FcPattern, sysroot: Option<&Path>) -> Option<FontFormat> {
+        let format = match &get_string(pat, FC_FONTFORMAT).ok()?[..] {
+            "TrueType" => FontFormat::TrueType,
+            "CFF" => FontFormat::Cff,
+            "Type 1" => FontFormat::Type1,
+            "PCF" => FontFormat::Pcf,
+            "BDF" => FontFormat::Bdf,
+            _ => return None,
+        };
+        if format == FontFormat::TrueType || format == FontFormat::Cff {
+            let file = file_path(pat, sysroot).ok().and_then(FontFormat::detect_file);
+            if let Some(web @ FontFormat::Woff) | Some(web @ FontFormat::Woff2) = file {
+                return Some(web);
             }
-            property.spacing.map(|spacing| add_int(pattern, FC_SPACING, spacing));
-            add_int(pattern, FC_WEIGHT, property.weight);
-            add_int(pattern, FC_SLANT, property.slant);
+        }
+        Some(format)
+    }
 
-            let null_ptr: *const c_char = ptr::null();
-            let o1 = FC_FAMILY.as_ptr() as *mut c_char;
-            let os = FcObjectSetBuild(o1, null_ptr);
-            let fs = FcFontList(config, pattern, os);
+    /// Whether the font is in one of the property's formats and scalable or not as requested
+    fn is_allowed(pat: *mut FcPattern, property: &FontProperty, sysroot: Option<&Path>) -> bool {
+        (property.formats().is_empty() || property.allows_format(font_format(pat, sysroot))) &&
+        property.allows_scalable(get_bool(pat, FC_SCALABLE).unwrap_or(true))
+    }
 
-            let patterns = slice::from_raw_parts((*fs).fonts, (*fs).nfont as usize);
-            for pat in patterns {
-                let family_name = get_string(*pat, FC_FAMILY).unwrap();
-                fonts.push(family_name);
+    /// Path of the font file. Fontconfig reports files inside a sysroot without it
+    fn file_path(pat: *mut FcPattern, sysroot: Option<&Path>) -> Result<PathBuf> {
+        Ok(in_sysroot(PathBuf::from(get_string(pat, FC_FILE)?), sysroot))
+    }
+
+    /// Whether the charset of the font contains all the characters
+    fn has_chars<I: IntoIterator<Item = char>>(pat: *mut FcPattern, chars: I) -> bool {
+        let mut charset: *mut FcCharSet = ptr::null_mut();
+        let object = FC_CHARSET.as_ptr() as *const c_char;
+        unsafe {
+            if FcPatternGetCharSet(pat, object, 0, &mut charset) != FcResultMatch {
+                return false;
             }
+            chars.into_iter().all(|c| FcCharSetHasChar(charset, c as u32) != 0)
         }
+    }
+
+    /// A path reported by fontconfig, which may lack the sysroot
+    fn in_sysroot(path: PathBuf, sysroot: Option<&Path>) -> PathBuf {
+        match sysroot {
+            Some(sysroot) if !path.starts_with(sysroot) => {
+                sysroot.join(path.strip_prefix("/").unwrap_or(&path))
+            }
+            _ => path,
+        }
+    }
 
-        fonts.sort();
-        fonts.dedup();
-        fonts
+    fn family_cstring(family: &str) -> Result<CString> {
+        CString::new(family).map_err(|_| FontLoaderError::InvalidFamilyName(family.to_owned()))
+    }
+
+    /// Name of a fontconfig object without its NUL terminator, for error reporting
+    fn object_str(object_name: &'static [u8]) -> &'static str {
+        let name = &object_name[..object_name.len() - 1];
+        ::std::str::from_utf8(name).unwrap_or("unknown")
     }
 
     fn add_int(pat: *mut FcPattern, object_name: &[u8], value: c_int) {
@@ -214,28 +1109,79 @@ pub mod system_fonts {
         }
     }
 
-    fn get_int(pat: *mut FcPattern, object_name: &[u8]) -> Result<c_int, &str> {
+    fn get_int(pat: *mut FcPattern, object_name: &'static [u8]) -> Result<c_int> {
         let object = object_name.as_ptr() as *const c_char;
         unsafe {
             let mut int: c_int = 0;
             if FcPatternGetInteger(pat, object, 0, &mut int) == FcResultMatch {
                 Ok(int)
             } else {
-                Err("Type didn't match")
+                Err(FontLoaderError::MissingProperty(object_str(object_name)))
             }
         }
     }
 
-    fn add_string(pat: *mut FcPattern, object_name: &[u8], value: &str) {
-        let value = CString::new(value).unwrap();
+    fn add_bool(pat: *mut FcPattern, object_name: &[u8], value: bool) {
+        let object = object_name.as_ptr() as *const c_char;
+        unsafe {
+            FcPatternAddBool(pat, object, value as FcBool);
+        }
+    }
+
+    fn add_double(pat: *mut FcPattern, object_name: &[u8], value: c_double) {
+        let object = object_name.as_ptr() as *const c_char;
+        unsafe {
+            FcPatternAddDouble(pat, object, value);
+        }
+    }
+
+    fn get_double(pat: *mut FcPattern, object_name: &'static [u8]) -> Result<c_double> {
+        let object = object_name.as_ptr() as *const c_char;
+        unsafe {
+            let mut value: c_double = 0.0;
+            if FcPatternGetDouble(pat, object, 0, &mut value) == FcResultMatch {
+                Ok(value)
+            } else {
+                Err(FontLoaderError::MissingProperty(object_str(object_name)))
+            }
+        }
+    }
+
+    fn get_bool(pat: *mut FcPattern, object_name: &'static [u8]) -> Result<bool> {
+        let object = object_name.as_ptr() as *const c_char;
+        unsafe {
+            let mut value: FcBool = 0;
+            if FcPatternGetBool(pat, object, 0, &mut value) == FcResultMatch {
+                Ok(value != 0)
+            } else {
+                Err(FontLoaderError::MissingProperty(object_str(object_name)))
+            }
+        }
+    }
+
+    fn add_charset(pat: *mut FcPattern, chars: &BTreeSet<char>) {
+        let object = FC_CHARSET.as_ptr() as *const c_char;
+        unsafe {
+            let charset = FcCharSetCreate();
+            for c in chars {
+                FcCharSetAddChar(charset, *c as u32);
+            }
+            FcPatternAddCharSet(pat, object, charset);
+            FcCharSetDestroy(charset);
+        }
+    }
+
+    fn add_string(pat: *mut FcPattern, object_name: &[u8], value: &str) -> Result<()> {
+        let value = family_cstring(value)?;
         let value_ptr = value.as_ptr() as *const FcChar8;
         let object = object_name.as_ptr() as *const c_char;
         unsafe {
             FcPatternAddString(pat, object, value_ptr);
         }
+        Ok(())
     }
 
-    fn get_string(pat: *mut FcPattern, object_name: &[u8]) -> Result<String, &str> {
+    fn get_string(pat: *mut FcPattern, object_name: &'static [u8]) -> Result<String> {
         unsafe {
             let mut string: *mut FcChar8 = ptr::null_mut();
             let object = object_name.as_ptr() as *const c_char;
@@ -244,7 +1190,81 @@ pub mod system_fonts {
                 let string = cstr.to_string_lossy().into_owned();
                 Ok(string)
             } else {
-                Err("Type didn't match")
+                Err(FontLoaderError::MissingProperty(object_str(object_name)))
+            }
+        }
+    }
+
+    /// All values of a string property, e.g. a family list
+    fn get_strings(pat: *mut FcPattern, object_name: &'static [u8]) -> Vec<String> {
+        let mut strings = Vec::new();
+        let object = object_name.as_ptr() as *const c_char;
+        unsafe {
+            let mut string: *mut FcChar8 = ptr::null_mut();
+            while FcPatternGetString(pat, object, strings.len() as c_int, &mut string) == FcResultMatch {
+                strings.push(CStr::from_ptr(string as *mut c_char).to_string_lossy().into_owned());
+            }
+        }
+        strings
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        extern "C" {
+            fn FcWeightFromOpenType(ot_weight: c_int) -> c_int;
+            fn FcWeightToOpenType(fc_weight: c_int) -> c_int;
+        }
+
+        #[test]
+        fn weights() {
+            let named = [(100, FC_WEIGHT_THIN), (400, FC_WEIGHT_REGULAR), (700, FC_WEIGHT_BOLD),
+                         (900, FC_WEIGHT_BLACK)];
+            for &(weight, fc) in &named {
+                assert_eq!(fc_weight(weight), fc);
+                assert_eq!(ot_weight(fc), weight);
+            }
+            for weight in 0..=1000 {
+                let fc = unsafe { FcWeightFromOpenType(c_int::from(weight)) };
+                assert_eq!(fc_weight(weight), fc, "weight {}", weight);
+            }
+            for fc in 0..=FC_WEIGHT_EXTRA_BLACK {
+                assert_eq!(c_int::from(ot_weight(fc)), unsafe { FcWeightToOpenType(fc) }, "weight {}", fc);
+            }
+        }
+
+        #[test]
+        fn uncovered_chars() {
+            // U+0378 is unassigned, so no face contains it
+            let property = FontPropertyBuilder::new().covers_char('\u{378}').build().unwrap();
+            assert!(matches!(locate(&property), Err(FontLoaderError::NoMatch)));
+            assert!(matches!(get(&property), Err(FontLoaderError::NoMatch)));
+        }
+
+        #[test]
+        fn languages() {
+            let tags = [
+                ("en", "en"),
+                ("EN-us", "en-us"),
+                ("pt-BR", "pt-br"),
+                ("pt_BR", "pt-br"),
+                ("es-419", "es-419"),
+                ("sr-Latn", "sr"),
+                ("sr-Cyrl-RS", "sr-rs"),
+                ("de-CH-1901", "de-ch"),
+                ("ja-u-ca-japanese", "ja"),
+                ("en-x-private", "en"),
+                ("zh", "zh-cn"),
+                ("zh-Hans", "zh-cn"),
+                ("zh-Hant", "zh-tw"),
+                ("zh-Hant-HK", "zh-hk"),
+                ("zh-TW", "zh-tw"),
+                ("zh-Hans-SG", "zh-sg"),
+                ("zh-Hant-US", "zh-tw"),
+            ];
+            for &(tag, lang) in &tags {
+                assert_eq!(fc_lang(tag), lang, "{:?}", tag);
             }
         }
     }
@@ -0,0 +1,212 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use data::FontData;
+use error::{FontLoaderError, Result};
+use sfnt::extract_face;
+#[cfg(feature = "woff")]
+use woff::{decode_woff, is_woff};
+
+use libc::c_int;
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::prelude::*;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Where the data of a face is read from
+#[derive(Clone)]
+pub(crate) enum Source {
+    File(PathBuf),
+    /// Data passed to `FontDatabase::load_font_data`, compared by identity
+    Memory(Arc<Vec<u8>>),
+}
+
+impl Source {
+    /// Path of the font file, empty for fonts loaded from memory
+    pub fn path(&self) -> &Path {
+        match *self {
+            Source::File(ref path) => path,
+            Source::Memory(_) => Path::new(""),
+        }
+    }
+
+    pub fn load(&self) -> Result<Vec<u8>> {
+        match *self {
+            Source::File(ref path) => read_file(path),
+            Source::Memory(ref data) => Ok(data.to_vec()),
+        }
+    }
+
+    pub fn load_data(&self) -> Result<FontData> {
+        match *self {
+            Source::File(ref path) => map_file(path),
+            Source::Memory(ref data) => Ok(FontData::shared(data.clone())),
+        }
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Source) -> bool {
+        match (self, other) {
+            (Source::File(a), Source::File(b)) => a == b,
+            (Source::Memory(a), Source::Memory(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Source {}
+
+impl Hash for Source {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Source::File(ref path) => path.hash(state),
+            Source::Memory(ref data) => (Arc::as_ptr(data) as usize).hash(state),
+        }
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Source::File(ref path) => path.fmt(f),
+            Source::Memory(ref data) => write!(f, "<{} bytes in memory>", data.len()),
+        }
+    }
+}
+
+/// A matched font face, resolved to its file without loading it
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontHandle {
+    source: Source,
+    index: c_int,
+    family: String,
+    style: String,
+    /// Bits of the `f32` size, so that handles can be compared and hashed
+    pixel_size: Option<u32>,
+}
+
+impl FontHandle {
+    pub(crate) fn new(source: Source, index: c_int, family: String, style: String) -> FontHandle {
+        FontHandle { source, index, family, style, pixel_size: None }
+    }
+
+    pub(crate) fn with_pixel_size(mut self, pixel_size: Option<f32>) -> FontHandle {
+        self.pixel_size = pixel_size.map(f32::to_bits);
+        self
+    }
+
+    /// Path of the font file, empty for fonts passed to `FontDatabase::load_font_data`
+    pub fn path(&self) -> &Path {
+        self.source.path()
+    }
+
+    /// Index of the face within the file, non-zero only for font collections
+    pub fn index(&self) -> c_int {
+        self.index
+    }
+
+    /// Family name of the matched face
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// Style name of the matched face, e.g. "Bold Italic"
+    pub fn style(&self) -> &str {
+        &self.style
+    }
+
+    /// Pixel size of the matched bitmap strike, `None` for scalable fonts
+    pub fn pixel_size(&self) -> Option<f32> {
+        self.pixel_size.map(f32::from_bits)
+    }
+
+    /// Read the binary data of the font file
+    pub fn load(&self) -> Result<Vec<u8>> {
+        self.source.load()
+    }
+
+    /// Load the font file, memory mapped if the `mmap` feature is enabled
+    pub fn load_data(&self) -> Result<FontData> {
+        self.source.load_data()
+    }
+
+    /// Load the face as a standalone font, extracted from its collection if it is part of one
+    pub fn load_face(&self) -> Result<Vec<u8>> {
+        extract_face(&self.load_data()?, self.index)
+    }
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = open_file(path)?;
+    let mut buf: Vec<u8> = Vec::new();
+    file.read_to_end(&mut buf)?;
+    unwrap_woff(buf)
+}
+
+pub(crate) fn map_file(path: &Path) -> Result<FontData> {
+    unwrap_woff(map_raw(path)?)
+}
+
+/// The font file as stored, without decoding WOFF and WOFF2
+#[cfg(feature = "mmap")]
+pub(crate) fn map_raw(path: &Path) -> Result<FontData> {
+    let file = open_file(path)?;
+    // Safety: font files are not expected to change while mapped, see `FontData`
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(FontData::mapped(map))
+}
+
+#[cfg(not(feature = "mmap"))]
+pub(crate) fn map_raw(path: &Path) -> Result<FontData> {
+    let mut buf: Vec<u8> = Vec::new();
+    open_file(path)?.read_to_end(&mut buf)?;
+    Ok(FontData::from(buf))
+}
+
+/// Decodes WOFF and WOFF2 fonts, so that every font is loaded as TrueType or OpenType
+#[cfg(feature = "woff")]
+pub(crate) fn unwrap_woff<T: AsRef<[u8]> + From<Vec<u8>>>(data: T) -> Result<T> {
+    if is_woff(data.as_ref()) {
+        decode_woff(data.as_ref()).map(T::from)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(not(feature = "woff"))]
+pub(crate) fn unwrap_woff<T: AsRef<[u8]> + From<Vec<u8>>>(data: T) -> Result<T> {
+    Ok(data)
+}
+
+pub(crate) fn open_file(path: &Path) -> Result<File> {
+    match File::open(path) {
+        Ok(file) => Ok(file),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => {
+            Err(FontLoaderError::MissingFile(path.to_path_buf()))
+        }
+        Err(err) => Err(FontLoaderError::Io(err)),
+    }
+}
@@ -0,0 +1,172 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use data::FontData;
+use error::Result;
+use handle::{FontHandle, Source};
+use property::{FontFormat, Slant, Spacing, Stretch};
+
+use libc::c_int;
+
+use std::path::Path;
+
+/// Metadata of a single installed font face
+///
+/// Names the backend doesn't provide are empty.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontInfo {
+    pub(crate) family: String,
+    pub(crate) style: String,
+    pub(crate) full_name: String,
+    pub(crate) postscript_name: String,
+    pub(crate) weight: u16,
+    pub(crate) slant: Slant,
+    pub(crate) stretch: Stretch,
+    pub(crate) spacing: Spacing,
+    pub(crate) source: Source,
+    pub(crate) index: c_int,
+    pub(crate) format: Option<FontFormat>,
+    pub(crate) scalable: bool,
+    /// Bits of the `f32` size, so that faces can be compared and hashed
+    pub(crate) pixel_size: Option<u32>,
+}
+
+impl FontInfo {
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// Style name, e.g. "Bold Italic"
+    pub fn style(&self) -> &str {
+        &self.style
+    }
+
+    /// Full name, e.g. "Roboto Bold Italic"
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    /// PostScript name, e.g. "Roboto-BoldItalic"
+    pub fn postscript_name(&self) -> &str {
+        &self.postscript_name
+    }
+
+    /// Weight on the CSS / OpenType scale, where 400 is regular and 700 is bold
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn slant(&self) -> Slant {
+        self.slant
+    }
+
+    pub fn stretch(&self) -> Stretch {
+        self.stretch
+    }
+
+    pub fn spacing(&self) -> Spacing {
+        self.spacing
+    }
+
+    /// Path of the font file, empty for fonts passed to `FontDatabase::load_font_data`
+    pub fn path(&self) -> &Path {
+        self.source.path()
+    }
+
+    /// Index of the face within the file, non-zero only for font collections
+    pub fn index(&self) -> c_int {
+        self.index
+    }
+
+    /// Format of the font file, `None` if it isn't one of `FontFormat`
+    pub fn format(&self) -> Option<FontFormat> {
+        self.format
+    }
+
+    /// Whether the face is an outline font, as opposed to a bitmap font
+    pub fn scalable(&self) -> bool {
+        self.scalable
+    }
+
+    /// Pixel size of the bitmap strike, `None` for scalable fonts
+    pub fn pixel_size(&self) -> Option<f32> {
+        self.pixel_size.map(f32::from_bits)
+    }
+
+    /// Handle to the face for loading its data
+    pub fn handle(&self) -> FontHandle {
+        FontHandle::new(self.source.clone(), self.index, self.family.clone(), self.style.clone())
+            .with_pixel_size(self.pixel_size())
+    }
+
+    /// Load the font file, memory mapped if the `mmap` feature is enabled
+    pub fn load_data(&self) -> Result<FontData> {
+        self.handle().load_data()
+    }
+}
+
+/// A style a family provides, as listed by `styles_of`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StyleInfo {
+    pub(crate) name: String,
+    pub(crate) weight: u16,
+    pub(crate) slant: Slant,
+    pub(crate) stretch: Stretch,
+}
+
+impl StyleInfo {
+    /// Style name, e.g. "SemiBold Condensed", for `FontPropertyBuilder::style`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Weight on the CSS / OpenType scale, where 400 is regular and 700 is bold
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn slant(&self) -> Slant {
+        self.slant
+    }
+
+    pub fn stretch(&self) -> Stretch {
+        self.stretch
+    }
+
+    /// The styles of the faces, ordered by stretch, weight and slant. Faces sharing a style
+    /// name, like the strikes of a bitmap font, give one style
+    pub(crate) fn of_faces(faces: &[FontInfo]) -> Vec<StyleInfo> {
+        let mut styles: Vec<StyleInfo> = Vec::new();
+        for face in faces {
+            let listed = styles.iter().any(|style| style.name.eq_ignore_ascii_case(&face.style));
+            if face.style.is_empty() || listed {
+                continue;
+            }
+            styles.push(StyleInfo {
+                name: face.style.clone(),
+                weight: face.weight,
+                slant: face.slant,
+                stretch: face.stretch,
+            });
+        }
+        styles.sort_by(|a, b| {
+            (a.stretch, a.weight, a.slant, &a.name).cmp(&(b.stretch, b.weight, b.slant, &b.name))
+        });
+        styles
+    }
+}
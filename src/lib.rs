@@ -34,6 +34,50 @@
 //! sudo apt-get install libfontconfig libfontconfig1-dev
 //! ```
 //!
+//! Without the default `fontconfig` feature, the font directories of the XDG Base Directory
+//! specification are scanned instead, which needs no C libraries. Only TrueType and OpenType
+//! fonts, and WOFF and WOFF2 fonts with the `woff` feature, are found this way and
+//! fontconfig's configuration, like family aliases, isn't applied.
+//!
+//! ```toml
+//! [dependencies]
+//! font-loader = { version = "0.11", default-features = false }
+//! ```
+//!
+//! # Application fonts
+//! Fonts bundled with an application can be loaded into a `FontDatabase`, optionally together
+//! with the system fonts, and are then matched with the same queries as `system_fonts`.
+//!
+//! # Collections
+//! Faces of TrueType and OpenType collections are returned with the whole collection and their
+//! index in it. `extract_face` turns such a face into a standalone font.
+//!
+//! # CSS
+//! `FontPropertyBuilder::css_family` and `FontPropertyBuilder::css_font` read CSS
+//! `font-family` lists and `font` shorthands, and `system_fonts::get_first_available` loads
+//! the first installed family of such a stack. `match_face` selects faces with the font
+//! matching algorithm of CSS Fonts Level 4 instead of the backend's own rules, so that
+//! `system_fonts::locate_css` picks the same face on every platform.
+//!
+//! # Documents
+//! PDF, SVG and Office documents reference fonts by PostScript name, like `Roboto-BoldItalic`,
+//! or by full name. `system_fonts::get_by_postscript_name` and `system_fonts::get_by_full_name`
+//! load exactly the face with that name, without substituting another one.
+//!
+//! # Styles
+//! `system_fonts::styles_of` lists the styles a family provides, like "SemiBold Condensed", and
+//! `FontPropertyBuilder::style` selects one of them by name.
+//!
+//! # Features
+//! * `fontconfig` (default): use fontconfig on Linux and Unix.
+//! * `mmap`: `FontHandle::load_data` memory maps font files instead of reading them into the
+//!   heap, so processes loading the same font share its pages.
+//! * `woff`: WOFF and WOFF2 web fonts are decoded to TrueType or OpenType when they are loaded,
+//!   through `get`, `FontHandle` or `FontDatabase::load_font_data`, and `decode_woff` is
+//!   available to decode them directly.
+//! * `watch`: `FontWatcher` reports fonts being installed or removed on Linux, after which
+//!   `system_fonts::rescan` picks them up.
+//!
 //! # Example
 //! ## Cargo.toml
 //! ```toml
@@ -48,26 +92,54 @@
 //! use fonts::system_fonts;
 //!
 //! fn main() {
-//! 	// Enumerate all fonts
-//!     let sysfonts = system_fonts::query_all();
+//!     // Enumerate all fonts
+//!     let sysfonts = system_fonts::query_all().unwrap();
 //!     for string in &sysfonts {
 //!         println!("{}", string);
 //!     }
 //!
-//! 	let mut property = system_fonts::FontPropertyBuilder::new().monospace().build();
-//! 	let sysfonts = system_fonts::query_specific(&mut property);
-//! 	for string in &sysfonts {
-//! 		println!("Monospaced font: {}", string);
-//! 	}
+//!     let property = system_fonts::FontPropertyBuilder::new().monospace().build().unwrap();
+//!     let sysfonts = system_fonts::query_specific(&property).unwrap();
+//!     for string in &sysfonts {
+//!         println!("Monospaced font: {}", string);
+//!     }
 //!
-//! 	let property = system_fonts::FontPropertyBuilder::new().family("Arial").build();
-//! 	let (font, _) = system_fonts::get(&property).unwrap();
-//! 	println!("{:?}", &font[..50]);
+//!     let property = system_fonts::FontPropertyBuilder::new().family("Arial").build().unwrap();
+//!     match system_fonts::get(&property) {
+//!         Ok((font, _)) => println!("{:?}", &font[..50]),
+//!         Err(err) => println!("Arial could not be loaded: {}", err),
+//!     }
 //! }
 //! ```
 
 
 extern crate libc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "woff")]
+extern crate brotli_decompressor;
+#[cfg(feature = "woff")]
+extern crate miniz_oxide;
+
+mod error;
+pub use error::{FontLoaderError, Result};
+
+mod property;
+mod css;
+mod matching;
+mod handle;
+mod data;
+mod info;
+mod sfnt;
+mod database;
+pub use data::FontData;
+pub use database::FontDatabase;
+pub use matching::match_face;
+pub use sfnt::{extract_face, face_count};
+#[cfg(feature = "woff")]
+mod woff;
+#[cfg(feature = "woff")]
+pub use woff::decode_woff;
 
 #[cfg(target_os = "windows")]
 extern crate winapi;
@@ -86,9 +158,21 @@ mod macos;
 #[cfg(target_os = "macos")]
 pub use macos::*;
 
-#[cfg(all(unix, not(target_os = "macos")))]
+#[cfg(all(unix, not(target_os = "macos"), feature = "fontconfig"))]
 extern crate fontconfig as servo_fontconfig;
-#[cfg(all(unix, not(target_os = "macos")))]
+#[cfg(all(unix, not(target_os = "macos"), feature = "fontconfig"))]
 mod fontconfig;
-#[cfg(all(unix, not(target_os = "macos")))]
+#[cfg(all(unix, not(target_os = "macos"), feature = "fontconfig"))]
 pub use fontconfig::*;
+
+#[cfg(all(unix, not(target_os = "macos"), not(feature = "fontconfig")))]
+mod scanner;
+#[cfg(all(unix, not(target_os = "macos"), not(feature = "fontconfig")))]
+pub use scanner::*;
+
+#[cfg(all(target_os = "linux", feature = "watch"))]
+extern crate inotify;
+#[cfg(all(target_os = "linux", feature = "watch"))]
+mod watch;
+#[cfg(all(target_os = "linux", feature = "watch"))]
+pub use watch::{FontChange, FontWatcher};
@@ -22,117 +22,387 @@ pub mod system_fonts {
     use core_text::font_descriptor;
     use core_text;
     use std::fs::File;
-    use std::mem;
+    use std::path::Path;
     use std::ptr;
     use core_foundation::string::CFString;
     use core_foundation::number::CFNumber;
     use core_foundation::array::CFArray;
     use core_foundation::dictionary::CFDictionary;
-    use core_foundation::base::{CFType, TCFType};
-    use core_foundation::url::CFURL;
+    use core_foundation::base::{kCFAllocatorDefault, CFType, TCFType};
+    use core_foundation::characterset::{CFCharacterSet, CFCharacterSetCreateWithCharactersInString};
     use libc::c_int;
-    use std::io::Read;
-    /// The platform specific font properties
-    pub type FontProperty = CTFontDescriptor;
 
-    /// Builder for FontProperty
-    pub struct FontPropertyBuilder {
-        symbolic_traits: CTFontSymbolicTraits,
-        family: String
-    }
+    use database::default_families;
+    use error::{FontLoaderError, Result};
+    use handle::Source;
+    use matching::locate_in_stack;
+    use sfnt;
+    pub use handle::FontHandle;
+    pub use info::{FontInfo, StyleInfo};
+    pub use property::{Family, FontProperty, FontPropertyBuilder, GenericFamily};
+    pub use property::{FontFormat, Slant, Spacing, Stretch};
 
-    impl FontPropertyBuilder {
-        pub fn new() -> FontPropertyBuilder {
-            FontPropertyBuilder{ symbolic_traits: 0, family: String::new()}
+    /// Translates the property into a `CTFontDescriptor`. Only the preferred family is used
+    fn descriptor(property: &FontProperty) -> CTFontDescriptor {
+        let mut symbolic_traits: CTFontSymbolicTraits = 0;
+        if property.slant() != Slant::Roman {
+            symbolic_traits |= kCTFontItalicTrait;
         }
-
-        pub fn italic(mut self) -> FontPropertyBuilder {
-            self.symbolic_traits |= kCTFontItalicTrait;
-            self
+        if property.spacing() == Some(Spacing::Monospace) {
+            symbolic_traits |= kCTFontMonoSpaceTrait;
+        }
+        if property.weight() >= 700 {
+            symbolic_traits |= kCTFontBoldTrait;
         }
+        if property.stretch() < Stretch::Normal {
+            symbolic_traits |= kCTFontCondensedTrait;
+        } else if property.stretch() > Stretch::Normal {
+            symbolic_traits |= kCTFontExpandedTrait;
+        }
+
+        let symbolic_traits_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontSymbolicTrait) };
+        let weight_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontWeightTrait) };
+        let width_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontWidthTrait) };
+        let traits = CFDictionary::from_CFType_pairs(&[
+            (symbolic_traits_attr.as_CFType(), CFNumber::from(symbolic_traits as i32).as_CFType()),
+            (weight_attr.as_CFType(), CFNumber::from(ct_weight(property.weight())).as_CFType()),
+            (width_attr.as_CFType(), CFNumber::from(ct_width(property.stretch())).as_CFType()),
+        ]);
+        family_descriptor(property, traits, property.style())
+    }
 
-        pub fn oblique(self) -> FontPropertyBuilder {
-            self.italic()
+    /// Translates the properties used for listing faces into a `CTFontDescriptor`
+    fn list_descriptor(property: &FontProperty) -> CTFontDescriptor {
+        let mut symbolic_traits: CTFontSymbolicTraits = 0;
+        if property.spacing() == Some(Spacing::Monospace) {
+            symbolic_traits |= kCTFontMonoSpaceTrait;
         }
+        let symbolic_traits_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontSymbolicTrait) };
+        let traits = CFDictionary::from_CFType_pairs(&[(symbolic_traits_attr.as_CFType(), CFNumber::from(symbolic_traits as i32).as_CFType())]);
+        family_descriptor(property, traits, None)
+    }
 
-        pub fn monospace(mut self) -> FontPropertyBuilder {
-            self.symbolic_traits |= kCTFontMonoSpaceTrait;
-            self
+    fn family_descriptor(property: &FontProperty, traits: CFDictionary<CFType, CFType>,
+                         style: Option<&str>) -> CTFontDescriptor {
+        let traits_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontTraitsAttribute) };
+        let mut attributes = Vec::new();
+        attributes.push((traits_attr, traits.as_CFType()));
+        if let Some(family) = family_names(property).first() {
+            let family_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontFamilyNameAttribute) };
+            attributes.push((family_attr, CFString::new(family).as_CFType()));
+        }
+        if let Some(style) = style {
+            let style_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontStyleNameAttribute) };
+            attributes.push((style_attr, CFString::new(style).as_CFType()));
+        }
+        if !property.chars().is_empty() {
+            let text: String = property.chars().iter().collect();
+            let charset_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontCharacterSetAttribute) };
+            let charset: CFCharacterSet = unsafe {
+                let text = CFString::new(&text);
+                let charset = CFCharacterSetCreateWithCharactersInString(kCFAllocatorDefault,
+                                                                         text.as_concrete_TypeRef());
+                TCFType::wrap_under_create_rule(charset)
+            };
+            attributes.push((charset_attr, charset.as_CFType()));
+        }
+        if let Some(language) = property.language() {
+            let languages_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontLanguagesAttribute) };
+            let languages = CFArray::from_CFTypes(&[CFString::new(language)]);
+            attributes.push((languages_attr, languages.as_CFType()));
         }
+        let attributes = CFDictionary::from_CFType_pairs(&attributes);
+        font_descriptor::new_from_attributes(&attributes)
+    }
+
+    /// Descriptors of all faces matching `config`
+    fn matching_descriptors(config: &CTFontDescriptor) -> Vec<CTFontDescriptor> {
+        let descs: CFArray<CTFontDescriptor> = unsafe {
+            let descs = CTFontDescriptorCreateMatchingFontDescriptors(
+                config.as_concrete_TypeRef(),
+                ptr::null(),
+            );
+            if descs.is_null() {
+                return Vec::new();
+            }
+            TCFType::wrap_under_create_rule(descs)
+        };
+        descs.iter().map(|desc| (*desc).clone()).collect()
+    }
 
-        pub fn bold(mut self) -> FontPropertyBuilder {
-            self.symbolic_traits |= kCTFontBoldTrait;
-            self
+    fn to_info(desc: &CTFontDescriptor) -> Option<FontInfo> {
+        let path = desc.font_path()?;
+        let traits = desc.traits();
+        let symbolic_traits = traits.symbolic_traits();
+        let format = desc.font_format().unwrap_or(kCTFontFormatUnrecognized);
+        let index = face_index(desc, &path);
+        Some(FontInfo {
+            family: desc.family_name(),
+            style: desc.style_name(),
+            full_name: desc.display_name(),
+            postscript_name: desc.font_name(),
+            weight: ot_weight(traits.normalized_weight()),
+            slant: if symbolic_traits.is_italic() { Slant::Italic } else { Slant::Roman },
+            stretch: Stretch::from_percentage((100.0 + traits.normalized_width() * 100.0).max(0.0) as u16),
+            spacing: if symbolic_traits.is_monospace() { Spacing::Monospace } else { Spacing::Proportional },
+            source: Source::File(path),
+            index,
+            format: font_format(format),
+            scalable: format != kCTFontFormatBitmap,
+            pixel_size: None,
+        })
+    }
+
+    fn font_format(format: CTFontFormat) -> Option<FontFormat> {
+        match format {
+            kCTFontFormatOpenTypePostScript => Some(FontFormat::Cff),
+            kCTFontFormatOpenTypeTrueType | kCTFontFormatTrueType => Some(FontFormat::TrueType),
+            kCTFontFormatPostScript => Some(FontFormat::Type1),
+            _ => None,
         }
+    }
+
+    /// Whether the face is in one of the property's formats and scalable or not as requested
+    fn is_allowed(desc: &CTFontDescriptor, property: &FontProperty) -> bool {
+        let format = desc.font_format().unwrap_or(kCTFontFormatUnrecognized);
+        property.allows_format(font_format(format)) && property.allows_scalable(format != kCTFontFormatBitmap)
+    }
+
+    /// OpenType weights paired with the matching `NSFontWeight` values used by Core Text
+    static WEIGHT_MAP: [(u16, f64); 10] = [
+        (0, -1.0),
+        (100, -0.8),
+        (200, -0.6),
+        (300, -0.4),
+        (400, 0.0),
+        (500, 0.23),
+        (600, 0.3),
+        (700, 0.4),
+        (800, 0.56),
+        (900, 0.62),
+    ];
 
-        pub fn family(mut self, name: &str) -> FontPropertyBuilder {
-            self.family = name.to_string();
-            self
+    /// Maps an OpenType weight onto Core Text's -1.0 to 1.0 weight trait
+    fn ct_weight(weight: u16) -> f64 {
+        if weight >= 900 {
+            return 0.62 + f64::from(weight - 900) / 100.0 * 0.38;
         }
+        let mut i = 1;
+        while weight > WEIGHT_MAP[i].0 {
+            i += 1;
+        }
+        let (ot_low, ct_low) = WEIGHT_MAP[i - 1];
+        let (ot_high, ct_high) = WEIGHT_MAP[i];
+        ct_low + f64::from(weight - ot_low) * (ct_high - ct_low) / f64::from(ot_high - ot_low)
+    }
 
-        pub fn build(self) -> FontProperty {
-            let family_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontFamilyNameAttribute) };
-            let family_name: CFString = self.family.parse().unwrap();
-            let traits_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontTraitsAttribute) };
-            let symbolic_traits_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontSymbolicTrait) };
-            let traits = CFDictionary::from_CFType_pairs(&[(symbolic_traits_attr.as_CFType(), CFNumber::from(self.symbolic_traits as i32).as_CFType())]);
-            let mut attributes = Vec::new();
-            attributes.push((traits_attr, traits.as_CFType()));
-            if self.family.len() != 0 {
-                attributes.push((family_attr, family_name.as_CFType()));
-            }
-            let attributes = CFDictionary::from_CFType_pairs(&attributes);
-            font_descriptor::new_from_attributes(&attributes)
+    /// Maps Core Text's weight trait back onto the OpenType scale
+    fn ot_weight(weight: f64) -> u16 {
+        if weight >= 0.62 {
+            return (900.0 + (weight - 0.62) / 0.38 * 100.0).min(1000.0) as u16;
+        }
+        let weight = weight.max(-1.0);
+        let mut i = 1;
+        while weight > WEIGHT_MAP[i].1 {
+            i += 1;
         }
+        let (ot_low, ct_low) = WEIGHT_MAP[i - 1];
+        let (ot_high, ct_high) = WEIGHT_MAP[i];
+        let ot = f64::from(ot_low) + (weight - ct_low) * f64::from(ot_high - ot_low) / (ct_high - ct_low);
+        (ot + 0.5) as u16
+    }
+
+    /// Maps a stretch onto Core Text's -1.0 to 1.0 width trait
+    fn ct_width(stretch: Stretch) -> f64 {
+        let width = (f64::from(stretch.percentage()) - 100.0) / 100.0;
+        width.max(-1.0).min(1.0)
+    }
+
+    /// The system tracks installed fonts itself and every query sees the current set,
+    /// so there is never anything to rescan
+    pub fn rescan() -> Result<bool> {
+        Ok(false)
     }
 
     /// Get the binary data and index of a specific font
-    pub fn get(config: &FontProperty) -> Option<(Vec<u8>, c_int)> {
-        let mut buffer = Vec::new();
-        let url: CFURL;
-        unsafe {
-            let value =
-                CTFontDescriptorCopyAttribute(config.as_concrete_TypeRef(), kCTFontURLAttribute);
-
-            if value.is_null() {
-                return None
-            }
+    pub fn get(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        let handle = locate(property)?;
+        Ok((handle.load()?, handle.index()))
+    }
 
-            let value: CFType = TCFType::wrap_under_get_rule(value);
-            if !value.instance_of::<CFURL>() {
-                return None
+    /// Resolve the file and face index of a specific font without reading it
+    pub fn locate(property: &FontProperty) -> Result<FontHandle> {
+        let config = descriptor(property);
+        // Core Text can't be asked for a format or scalability, so the first allowed matching
+        // face is taken instead
+        if !property.formats().is_empty() || property.scalable().is_some() {
+            return match matching_descriptors(&config).iter().find(|desc| is_allowed(desc, property)) {
+                Some(desc) => to_handle(desc),
+                None => Err(FontLoaderError::NoMatch),
+            };
+        }
+        let matched: CTFontDescriptor = unsafe {
+            let matched =
+                CTFontDescriptorCreateMatchingFontDescriptor(config.as_concrete_TypeRef(), ptr::null());
+            if matched.is_null() {
+                return Err(FontLoaderError::NoMatch)
             }
-            url = TCFType::wrap_under_get_rule(mem::transmute(value.as_CFTypeRef()));
+            TCFType::wrap_under_create_rule(matched)
+        };
+        to_handle(&matched)
+    }
+
+    /// Find the best installed face containing the character, preferring faces close to the
+    /// requested properties
+    pub fn fallback_for(c: char, property: &FontProperty) -> Result<FontHandle> {
+        let allowed = |descs: Vec<CTFontDescriptor>| -> Vec<CTFontDescriptor> {
+            descs.into_iter().filter(|desc| is_allowed(desc, property)).collect()
+        };
+        let mut covering = property.clone();
+        covering.chars.insert(c);
+        let mut descs = allowed(matching_descriptors(&descriptor(&covering)));
+        if descs.is_empty() {
+            covering.families.clear();
+            descs = allowed(matching_descriptors(&descriptor(&covering)));
+        }
+        match descs.first() {
+            Some(desc) => to_handle(desc),
+            None => Err(FontLoaderError::NoMatch),
         }
-        if let Some(path) = url.to_path() {
-            match File::open(path).and_then(|mut f| f.read_to_end(&mut buffer)) {
-                Ok(_) => return Some((buffer, 0)),
-                Err(_) => return None,
+    }
+
+    /// The styles the installed family provides, e.g. to let users pick one by name for
+    /// `FontPropertyBuilder::style`
+    pub fn styles_of(family: &str) -> Result<Vec<StyleInfo>> {
+        let property = FontPropertyBuilder::new().family(family).build()?;
+        Ok(StyleInfo::of_faces(&query_faces(&property)?))
+    }
+
+    /// Get the binary data and index of the first family of the property which is installed,
+    /// as CSS walks a font stack. Unlike `get`, no other family is substituted, so this fails
+    /// with `NoMatch` if none of them is installed
+    pub fn get_first_available(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        if property.families().is_empty() {
+            return get(property);
+        }
+        for single in property.family_stack(resolve_generic) {
+            if !query_faces(&single)?.is_empty() {
+                return get(&single);
             }
+        }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Resolve the file and face index of the face CSS Fonts Level 4 font matching selects,
+    /// among the faces of the first family of the property which is installed. Unlike
+    /// `locate`, the result doesn't depend on the platform
+    pub fn locate_css(property: &FontProperty) -> Result<FontHandle> {
+        locate_in_stack(property, resolve_generic, || locate(property), query_faces)
+    }
+
+    /// Get the binary data and index of the installed face with the PostScript name, such as
+    /// `Roboto-BoldItalic`, as documents reference fonts. No other face is substituted, names
+    /// are compared ignoring ASCII case
+    pub fn get_by_postscript_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        let name_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontNameAttribute) };
+        get_by_name(name_attr, name, CTFontDescriptor::font_name)
+    }
+
+    /// Get the binary data and index of the installed face with the full name, such as
+    /// `Roboto Bold Italic`. No other face is substituted, names are compared ignoring ASCII
+    /// case
+    pub fn get_by_full_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        let name_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontDisplayNameAttribute) };
+        get_by_name(name_attr, name, CTFontDescriptor::display_name)
+    }
+
+    /// Core Text substitutes a similar face for unknown names, so the matched faces are checked
+    fn get_by_name<F>(name_attr: CFString, name: &str, desc_name: F) -> Result<(Vec<u8>, c_int)>
+        where F: Fn(&CTFontDescriptor) -> String
+    {
+        let attributes = CFDictionary::from_CFType_pairs(&[(name_attr, CFString::new(name).as_CFType())]);
+        let config = font_descriptor::new_from_attributes(&attributes);
+        let handle = match matching_descriptors(&config).iter().find(|desc| desc_name(desc).eq_ignore_ascii_case(name)) {
+            Some(desc) => to_handle(desc)?,
+            None => return Err(FontLoaderError::NoMatch),
         };
-        return None
+        Ok((handle.load()?, handle.index()))
+    }
+
+    /// Resolve a generic family to the installed families commonly used for it, most
+    /// preferred first
+    pub fn resolve_generic(generic: GenericFamily) -> Vec<String> {
+        let installed = query_all().unwrap_or_default();
+        default_families(generic)
+            .iter()
+            .filter_map(|candidate| installed.iter().find(|family| family.eq_ignore_ascii_case(candidate)))
+            .cloned()
+            .collect()
+    }
+
+    /// The requested family names, with generic families resolved
+    fn family_names(property: &FontProperty) -> Vec<String> {
+        property.resolve_generics(resolve_generic)
+            .families
+            .iter()
+            .map(|family| family.name().to_string())
+            .collect()
+    }
+
+    fn to_handle(desc: &CTFontDescriptor) -> Result<FontHandle> {
+        let path = match desc.font_path() {
+            Some(path) => path,
+            None => return Err(FontLoaderError::MissingProperty("url")),
+        };
+        let index = face_index(desc, &path);
+        Ok(FontHandle::new(Source::File(path), index, desc.family_name(), desc.style_name()))
+    }
+
+    /// Index of the descriptor's face within its file. Core Text doesn't report it, so the faces
+    /// of a collection are told apart by their PostScript name. Only the `name` tables are read
+    fn face_index(desc: &CTFontDescriptor, path: &Path) -> c_int {
+        let faces = match File::open(path).and_then(|mut file| sfnt::read_faces(&mut file, &[b"name"])) {
+            Ok(faces) => faces,
+            Err(_) => return 0,
+        };
+        if faces.len() <= 1 {
+            return 0;
+        }
+        let postscript_name = desc.font_name();
+        faces.iter()
+            .find(|face| face.postscript_name == postscript_name)
+            .map_or(0, |face| face.index as c_int)
     }
 
     /// Query the names of all fonts installed in the system
-    pub fn query_all() -> Vec<String> {
-        core_text::font_collection::get_family_names()
+    pub fn query_all() -> Result<Vec<String>> {
+        Ok(core_text::font_collection::get_family_names()
             .iter()
             .map(|family_name| family_name.to_string())
-            .collect()
+            .collect())
     }
 
     /// Query the names of specifc fonts installed in the system
-    pub fn query_specific(property: &mut FontProperty) -> Vec<String> {
-        let descs: CFArray<CTFontDescriptor> = unsafe {
-            let descs = CTFontDescriptorCreateMatchingFontDescriptors(
-                property.as_concrete_TypeRef(),
-                ptr::null(),
-            );
-            TCFType::wrap_under_create_rule(descs)
-        };
-        descs
+    pub fn query_specific(property: &FontProperty) -> Result<Vec<String>> {
+        Ok(matching_descriptors(&descriptor(property))
             .iter()
+            .filter(|desc| is_allowed(desc, property))
             .map(|desc| desc.family_name())
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>())
+    }
+
+    /// Query every face of the fonts matching the property's families and spacing.
+    /// Weight, slant and stretch are ignored, so that all styles of a family are returned
+    pub fn query_faces(property: &FontProperty) -> Result<Vec<FontInfo>> {
+        let mut faces: Vec<FontInfo> = matching_descriptors(&list_descriptor(property))
+            .iter()
+            .filter_map(to_info)
+            .filter(|face| property.allows_format(face.format) && property.allows_scalable(face.scalable))
+            .collect();
+        faces.sort_by(|a, b| {
+            (&a.family, a.stretch, a.weight, a.slant, a.path(), a.index)
+                .cmp(&(&b.family, b.stretch, b.weight, b.slant, b.path(), b.index))
+        });
+        Ok(faces)
     }
 }
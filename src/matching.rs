@@ -0,0 +1,265 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use error::{FontLoaderError, Result};
+use handle::FontHandle;
+use info::FontInfo;
+use property::{Family, FontProperty, GenericFamily, Slant};
+
+/// Select the face the CSS Fonts Level 4 font matching algorithm (§5.2) picks for the
+/// property among the faces of one family
+///
+/// Faces are narrowed down by stretch, then by slant and finally by weight, each time keeping
+/// the faces closest to the requested value in the direction the specification prefers, e.g.
+/// narrower faces before wider ones for condensed requests. Remaining ties go to the face
+/// listed first. Families, characters and languages of the property aren't considered.
+pub fn match_face<'a>(faces: &'a [FontInfo], property: &FontProperty) -> Option<&'a FontInfo> {
+    let mut candidates: Vec<&FontInfo> = faces.iter().collect();
+    let stretch = property.stretch().percentage();
+    narrow(&mut candidates, |face| stretch_key(stretch, face.stretch.percentage()));
+    narrow(&mut candidates, |face| slant_key(property.slant(), face.slant));
+    narrow(&mut candidates, |face| weight_key(property.weight(), face.weight));
+    candidates.first().cloned()
+}
+
+/// Runs `match_face` over the faces of the first family of the property which has any. Without
+/// families, the family of the backend's default match is used
+pub(crate) fn locate_in_stack<R, D, Q>(property: &FontProperty, resolve: R, default: D,
+                                       mut query_faces: Q) -> Result<FontHandle>
+    where R: FnMut(GenericFamily) -> Vec<String>,
+          D: FnOnce() -> Result<FontHandle>,
+          Q: FnMut(&FontProperty) -> Result<Vec<FontInfo>>
+{
+    let stack = if property.families().is_empty() {
+        let mut single = property.clone();
+        single.families = vec![Family::Name(default()?.family().to_string())];
+        vec![single]
+    } else {
+        property.family_stack(resolve)
+    };
+    for single in &stack {
+        let faces = query_faces(single)?;
+        if let Some(face) = match_face(&faces, single) {
+            return Ok(face.handle());
+        }
+    }
+    Err(FontLoaderError::NoMatch)
+}
+
+/// Keeps the candidates with the lowest key
+fn narrow<K: Ord, F: Fn(&FontInfo) -> K>(candidates: &mut Vec<&FontInfo>, key: F) {
+    if let Some(best) = candidates.iter().map(|face| key(face)).min() {
+        candidates.retain(|face| key(face) == best);
+    }
+}
+
+/// Up to normal width, narrower faces are preferred and wider ones otherwise
+fn stretch_key(requested: u16, actual: u16) -> (u8, u16) {
+    let preferred = if requested <= 100 { actual <= requested } else { actual >= requested };
+    (if preferred { 0 } else { 1 }, actual.abs_diff(requested))
+}
+
+/// Italic falls back on oblique and oblique on italic, before either falls back on roman.
+/// Roman falls back on oblique before italic
+fn slant_key(requested: Slant, actual: Slant) -> u8 {
+    let order = match requested {
+        Slant::Roman => [Slant::Roman, Slant::Oblique, Slant::Italic],
+        Slant::Italic => [Slant::Italic, Slant::Oblique, Slant::Roman],
+        Slant::Oblique => [Slant::Oblique, Slant::Italic, Slant::Roman],
+    };
+    order.iter().position(|&slant| slant == actual).unwrap_or(order.len()) as u8
+}
+
+/// Between 400 and 500, heavier faces up to 500 are tried first, then lighter ones and then
+/// those above 500. Below 400 lighter faces are preferred and above 500 heavier ones
+fn weight_key(requested: u16, actual: u16) -> (u8, u16) {
+    let distance = actual.abs_diff(requested);
+    if (400..=500).contains(&requested) {
+        if actual >= requested && actual <= 500 {
+            (0, distance)
+        } else if actual < requested {
+            (1, distance)
+        } else {
+            (2, distance)
+        }
+    } else if requested < 400 {
+        (if actual <= requested { 0 } else { 1 }, distance)
+    } else {
+        (if actual >= requested { 0 } else { 1 }, distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use handle::Source;
+    use property::{FontPropertyBuilder, Spacing, Stretch};
+
+    use std::path::PathBuf;
+
+    fn face(family: &str, weight: u16, slant: Slant, stretch: Stretch) -> FontInfo {
+        FontInfo {
+            family: family.to_string(),
+            style: format!("{} {:?} {:?}", weight, slant, stretch),
+            full_name: String::new(),
+            postscript_name: String::new(),
+            weight,
+            slant,
+            stretch,
+            spacing: Spacing::Proportional,
+            source: Source::File(PathBuf::from(format!("{}-{}.ttf", family, weight))),
+            index: 0,
+            format: None,
+            scalable: true,
+            pixel_size: None,
+        }
+    }
+
+    fn weights(weights: &[u16]) -> Vec<FontInfo> {
+        weights.iter().map(|&weight| face("A", weight, Slant::Roman, Stretch::Normal)).collect()
+    }
+
+    fn matched_weight(faces: &[FontInfo], weight: u16) -> u16 {
+        let property = FontPropertyBuilder::new().weight(weight).build().unwrap();
+        match_face(faces, &property).unwrap().weight()
+    }
+
+    #[test]
+    fn weight_below_400_prefers_lighter() {
+        let faces = weights(&[100, 200, 500, 700]);
+        assert_eq!(matched_weight(&faces, 300), 200);
+        assert_eq!(matched_weight(&faces, 200), 200);
+        assert_eq!(matched_weight(&weights(&[500, 700]), 300), 500);
+    }
+
+    #[test]
+    fn weight_between_400_and_500() {
+        // Heavier up to 500 first, then lighter, then above 500
+        assert_eq!(matched_weight(&weights(&[300, 500, 600]), 400), 500);
+        assert_eq!(matched_weight(&weights(&[300, 600]), 400), 300);
+        assert_eq!(matched_weight(&weights(&[600, 900]), 450), 600);
+        assert_eq!(matched_weight(&weights(&[400, 600]), 500), 400);
+    }
+
+    #[test]
+    fn weight_above_500_prefers_heavier() {
+        let faces = weights(&[100, 500, 800, 900]);
+        assert_eq!(matched_weight(&faces, 600), 800);
+        assert_eq!(matched_weight(&weights(&[100, 400, 500]), 700), 500);
+    }
+
+    fn matched_stretch(faces: &[FontInfo], stretch: Stretch) -> Stretch {
+        let property = FontPropertyBuilder::new().stretch(stretch).build().unwrap();
+        match_face(faces, &property).unwrap().stretch()
+    }
+
+    #[test]
+    fn stretch_prefers_narrower_up_to_normal() {
+        let stretches = |stretches: &[Stretch]| -> Vec<FontInfo> {
+            stretches.iter().map(|&stretch| face("A", 400, Slant::Roman, stretch)).collect()
+        };
+        let faces = stretches(&[Stretch::ExtraCondensed, Stretch::SemiExpanded]);
+        assert_eq!(matched_stretch(&faces, Stretch::Condensed), Stretch::ExtraCondensed);
+        assert_eq!(matched_stretch(&faces, Stretch::Normal), Stretch::ExtraCondensed);
+        let faces = stretches(&[Stretch::Expanded]);
+        assert_eq!(matched_stretch(&faces, Stretch::Condensed), Stretch::Expanded);
+
+        let faces = stretches(&[Stretch::ExtraCondensed, Stretch::UltraExpanded]);
+        assert_eq!(matched_stretch(&faces, Stretch::Expanded), Stretch::UltraExpanded);
+        let faces = stretches(&[Stretch::Condensed, Stretch::SemiExpanded]);
+        assert_eq!(matched_stretch(&faces, Stretch::ExtraExpanded), Stretch::SemiExpanded);
+    }
+
+    #[test]
+    fn italic_falls_back_on_oblique_then_roman() {
+        let italic = FontPropertyBuilder::new().italic().build().unwrap();
+        let slants = |slants: &[Slant]| -> Vec<FontInfo> {
+            slants.iter().map(|&slant| face("A", 400, slant, Stretch::Normal)).collect()
+        };
+        let faces = slants(&[Slant::Roman, Slant::Oblique, Slant::Italic]);
+        assert_eq!(match_face(&faces, &italic).unwrap().slant(), Slant::Italic);
+        let faces = slants(&[Slant::Roman, Slant::Oblique]);
+        assert_eq!(match_face(&faces, &italic).unwrap().slant(), Slant::Oblique);
+        let faces = slants(&[Slant::Roman]);
+        assert_eq!(match_face(&faces, &italic).unwrap().slant(), Slant::Roman);
+
+        let roman = FontPropertyBuilder::new().build().unwrap();
+        let faces = slants(&[Slant::Italic, Slant::Oblique]);
+        assert_eq!(match_face(&faces, &roman).unwrap().slant(), Slant::Oblique);
+    }
+
+    #[test]
+    fn stretch_before_slant_before_weight() {
+        let faces = vec![
+            face("A", 400, Slant::Italic, Stretch::Condensed),
+            face("A", 700, Slant::Roman, Stretch::Normal),
+            face("A", 400, Slant::Roman, Stretch::Normal),
+        ];
+        let property = FontPropertyBuilder::new().bold().italic().build().unwrap();
+        assert_eq!(match_face(&faces, &property), Some(&faces[1]));
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_face() {
+        let faces = vec![
+            face("A", 300, Slant::Roman, Stretch::Normal),
+            face("B", 300, Slant::Roman, Stretch::Normal),
+        ];
+        let property = FontPropertyBuilder::new().weight(300).build().unwrap();
+        assert_eq!(match_face(&faces, &property), Some(&faces[0]));
+        let reversed: Vec<FontInfo> = faces.iter().rev().cloned().collect();
+        assert_eq!(match_face(&reversed, &property), Some(&faces[1]));
+        assert_eq!(match_face(&[], &property), None);
+    }
+
+    #[test]
+    fn stack_uses_the_first_family_with_faces() {
+        let property = FontPropertyBuilder::new()
+            .family("Missing")
+            .generic(GenericFamily::Serif)
+            .bold()
+            .build()
+            .unwrap();
+        let query_faces = |single: &FontProperty| {
+            Ok(match single.family() {
+                Some("B") => vec![face("B", 400, Slant::Roman, Stretch::Normal),
+                                  face("B", 700, Slant::Roman, Stretch::Normal)],
+                _ => Vec::new(),
+            })
+        };
+        let handle = locate_in_stack(&property, |_| vec!["B".to_string()], || Err(FontLoaderError::NoMatch),
+                                     query_faces).unwrap();
+        assert_eq!((handle.family(), handle.style()), ("B", "700 Roman Normal"));
+
+        let missing = FontPropertyBuilder::new().family("Missing").build().unwrap();
+        let result = locate_in_stack(&missing, |_| Vec::new(), || Err(FontLoaderError::NoMatch),
+                                     |_| Ok(Vec::new()));
+        assert!(matches!(result, Err(FontLoaderError::NoMatch)));
+    }
+
+    #[test]
+    fn stack_without_families_uses_the_default_family() {
+        let property = FontPropertyBuilder::new().italic().build().unwrap();
+        let default = face("C", 400, Slant::Roman, Stretch::Normal);
+        let handle = locate_in_stack(&property, |_| Vec::new(), || Ok(default.handle()), |single| {
+            assert_eq!(single.family(), Some("C"));
+            Ok(vec![default.clone(), face("C", 400, Slant::Italic, Stretch::Normal)])
+        }).unwrap();
+        assert_eq!(handle.style(), "400 Italic Normal");
+    }
+}
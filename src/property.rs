@@ -0,0 +1,712 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use css;
+use error::{FontLoaderError, Result};
+use sfnt;
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Slant of a font face
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Slant {
+    Roman,
+    Italic,
+    Oblique,
+}
+
+/// Width of a font face relative to its normal width
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stretch {
+    UltraCondensed,
+    ExtraCondensed,
+    Condensed,
+    SemiCondensed,
+    Normal,
+    SemiExpanded,
+    Expanded,
+    ExtraExpanded,
+    UltraExpanded,
+}
+
+impl Stretch {
+    /// Width in percent of the normal width, as used by CSS and fontconfig
+    pub fn percentage(self) -> u16 {
+        match self {
+            Stretch::UltraCondensed => 50,
+            Stretch::ExtraCondensed => 63,
+            Stretch::Condensed => 75,
+            Stretch::SemiCondensed => 87,
+            Stretch::Normal => 100,
+            Stretch::SemiExpanded => 113,
+            Stretch::Expanded => 125,
+            Stretch::ExtraExpanded => 150,
+            Stretch::UltraExpanded => 200,
+        }
+    }
+
+    /// The stretch closest to a width in percent of the normal width
+    pub fn from_percentage(percentage: u16) -> Stretch {
+        let all = [
+            Stretch::UltraCondensed,
+            Stretch::ExtraCondensed,
+            Stretch::Condensed,
+            Stretch::SemiCondensed,
+            Stretch::Normal,
+            Stretch::SemiExpanded,
+            Stretch::Expanded,
+            Stretch::ExtraExpanded,
+            Stretch::UltraExpanded,
+        ];
+        *all.iter()
+            .min_by_key(|stretch| (i32::from(stretch.percentage()) - i32::from(percentage)).abs())
+            .unwrap()
+    }
+}
+
+/// Horizontal spacing of a font face
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Spacing {
+    Proportional,
+    /// Glyphs are either one or two cells wide, as in CJK monospace fonts
+    Dual,
+    Monospace,
+    /// Glyphs are confined to a fixed cell, as in terminal bitmap fonts
+    CharCell,
+}
+
+/// File format of a font
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FontFormat {
+    /// TrueType, or OpenType with TrueType outlines
+    TrueType,
+    /// OpenType with PostScript outlines in a CFF or CFF2 table
+    Cff,
+    /// PostScript Type 1, as PFA or PFB file
+    Type1,
+    /// X11 Portable Compiled Format bitmap font
+    Pcf,
+    /// Glyph Bitmap Distribution Format bitmap font
+    Bdf,
+    Woff,
+    Woff2,
+}
+
+impl FontFormat {
+    /// Detects the format of font data by its magic bytes. Collections have the format of their
+    /// first face. Other formats give `None`
+    pub fn detect(data: &[u8]) -> Option<FontFormat> {
+        match data.get(..4)? {
+            b"\0\x01\0\0" | b"true" => Some(FontFormat::TrueType),
+            b"OTTO" => Some(FontFormat::Cff),
+            b"ttcf" => {
+                let offset = sfnt::read_u32(data, 12)? as usize;
+                match data.get(offset..)?.get(..4)? {
+                    b"\0\x01\0\0" | b"true" => Some(FontFormat::TrueType),
+                    b"OTTO" => Some(FontFormat::Cff),
+                    _ => None,
+                }
+            }
+            b"wOFF" => Some(FontFormat::Woff),
+            b"wOF2" => Some(FontFormat::Woff2),
+            b"\x01fcp" => Some(FontFormat::Pcf),
+            _ if data.starts_with(b"STARTFONT") => Some(FontFormat::Bdf),
+            // PFB files wrap the PostScript program in segments starting with 0x80 0x01
+            _ if data.starts_with(b"%!PS-AdobeFont") || data.starts_with(b"%!FontType1") ||
+                 data.starts_with(b"\x80\x01") => Some(FontFormat::Type1),
+            _ => None,
+        }
+    }
+
+    /// Detects the format of a font file by its magic bytes, reading only its start
+    pub fn detect_file<P: AsRef<Path>>(path: P) -> Option<FontFormat> {
+        let mut file = File::open(path).ok()?;
+        let mut head = Vec::with_capacity(64);
+        (&mut file).take(64).read_to_end(&mut head).ok()?;
+        if head.starts_with(b"ttcf") {
+            // The first face of a collection may start anywhere
+            let offset = sfnt::read_u32(&head, 12)?;
+            let mut version = [0; 4];
+            file.seek(SeekFrom::Start(u64::from(offset))).ok()?;
+            file.read_exact(&mut version).ok()?;
+            return match FontFormat::detect(&version)? {
+                format @ FontFormat::TrueType | format @ FontFormat::Cff => Some(format),
+                _ => None,
+            };
+        }
+        FontFormat::detect(&head)
+    }
+}
+
+/// Generic font families of CSS, standing for whatever concrete families the system is
+/// configured to use for them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GenericFamily {
+    Serif,
+    SansSerif,
+    Monospace,
+    Cursive,
+    Fantasy,
+    Emoji,
+    /// The font of the platform's user interface
+    SystemUi,
+}
+
+impl GenericFamily {
+    /// The CSS keyword, e.g. "sans-serif". Fontconfig uses the same names for its aliases
+    pub fn keyword(self) -> &'static str {
+        match self {
+            GenericFamily::Serif => "serif",
+            GenericFamily::SansSerif => "sans-serif",
+            GenericFamily::Monospace => "monospace",
+            GenericFamily::Cursive => "cursive",
+            GenericFamily::Fantasy => "fantasy",
+            GenericFamily::Emoji => "emoji",
+            GenericFamily::SystemUi => "system-ui",
+        }
+    }
+
+    /// The generic family of a CSS keyword, ignoring ASCII case
+    pub fn from_keyword(keyword: &str) -> Option<GenericFamily> {
+        let all = [
+            GenericFamily::Serif,
+            GenericFamily::SansSerif,
+            GenericFamily::Monospace,
+            GenericFamily::Cursive,
+            GenericFamily::Fantasy,
+            GenericFamily::Emoji,
+            GenericFamily::SystemUi,
+        ];
+        all.iter().cloned().find(|generic| generic.keyword().eq_ignore_ascii_case(keyword))
+    }
+}
+
+/// A requested font family, either by name or generic
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Family {
+    Name(String),
+    Generic(GenericFamily),
+}
+
+impl Family {
+    /// The family name, or the CSS keyword of a generic family
+    pub fn name(&self) -> &str {
+        match *self {
+            Family::Name(ref name) => name,
+            Family::Generic(generic) => generic.keyword(),
+        }
+    }
+}
+
+/// Platform independent font properties, translated by each backend to its native query form
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontProperty {
+    pub(crate) families: Vec<Family>,
+    pub(crate) weight: u16,
+    pub(crate) slant: Slant,
+    pub(crate) stretch: Stretch,
+    pub(crate) spacing: Option<Spacing>,
+    pub(crate) chars: BTreeSet<char>,
+    pub(crate) language: Option<String>,
+    pub(crate) formats: Vec<FontFormat>,
+    pub(crate) scalable: Option<bool>,
+    /// Bits of the `f32` size, so that properties can be compared and hashed
+    pub(crate) pixel_size: Option<u32>,
+    pub(crate) style: Option<String>,
+}
+
+impl FontProperty {
+    /// Requested families, in order of preference. Empty matches any family
+    pub fn families(&self) -> &[Family] {
+        &self.families
+    }
+
+    /// Name of the preferred family, if any. Generic families give their CSS keyword
+    pub fn family(&self) -> Option<&str> {
+        self.families.first().map(Family::name)
+    }
+
+    /// Weight on the CSS / OpenType scale, where 400 is regular and 700 is bold
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn slant(&self) -> Slant {
+        self.slant
+    }
+
+    pub fn stretch(&self) -> Stretch {
+        self.stretch
+    }
+
+    pub fn spacing(&self) -> Option<Spacing> {
+        self.spacing
+    }
+
+    /// Characters a matching face has to contain
+    pub fn chars(&self) -> &BTreeSet<char> {
+        &self.chars
+    }
+
+    /// BCP 47 tag of the language the text is written in
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_ref().map(|language| &language[..])
+    }
+
+    /// Formats a matching face has to be in. Empty allows any format
+    pub fn formats(&self) -> &[FontFormat] {
+        &self.formats
+    }
+
+    /// Whether a face of the format, `None` if it isn't one of `FontFormat`, may match
+    pub(crate) fn allows_format(&self, format: Option<FontFormat>) -> bool {
+        self.formats.is_empty() || format.is_some_and(|format| self.formats.contains(&format))
+    }
+
+    /// Whether matching faces have to be scalable outline fonts or bitmap fonts, if either
+    pub fn scalable(&self) -> Option<bool> {
+        self.scalable
+    }
+
+    /// Whether a scalable or bitmap face may match
+    pub(crate) fn allows_scalable(&self, scalable: bool) -> bool {
+        self.scalable.is_none_or(|required| required == scalable)
+    }
+
+    /// Pixel size bitmap strikes are matched against
+    pub fn pixel_size(&self) -> Option<f32> {
+        self.pixel_size.map(f32::from_bits)
+    }
+
+    /// Style name of the face within its family, e.g. "SemiBold Condensed Italic"
+    pub fn style(&self) -> Option<&str> {
+        self.style.as_ref().map(|style| &style[..])
+    }
+
+    /// The property with only one of its families at a time, in order of preference and with
+    /// generic families resolved
+    pub(crate) fn family_stack<F>(&self, resolve: F) -> Vec<FontProperty>
+        where F: FnMut(GenericFamily) -> Vec<String>
+    {
+        let resolved = self.resolve_generics(resolve);
+        resolved.families
+            .iter()
+            .map(|family| {
+                let mut single = resolved.clone();
+                single.families = vec![family.clone()];
+                single
+            })
+            .collect()
+    }
+
+    /// Copy of the property with each generic family replaced by the families it resolves to
+    pub(crate) fn resolve_generics<F>(&self, mut resolve: F) -> FontProperty
+        where F: FnMut(GenericFamily) -> Vec<String>
+    {
+        let mut property = self.clone();
+        property.families.clear();
+        for family in &self.families {
+            match *family {
+                Family::Name(ref name) => property.families.push(Family::Name(name.clone())),
+                Family::Generic(generic) => {
+                    property.families.extend(resolve(generic).into_iter().map(Family::Name))
+                }
+            }
+        }
+        property
+    }
+}
+
+/// Builder for FontProperty
+#[derive(Clone, Debug)]
+pub struct FontPropertyBuilder {
+    property: FontProperty,
+    invalid_css: Option<String>,
+}
+
+impl Default for FontPropertyBuilder {
+    fn default() -> FontPropertyBuilder {
+        FontPropertyBuilder::new()
+    }
+}
+
+impl FontPropertyBuilder {
+    pub fn new() -> FontPropertyBuilder {
+        let property = FontProperty {
+            families: Vec::new(),
+            weight: 400,
+            slant: Slant::Roman,
+            stretch: Stretch::Normal,
+            spacing: None,
+            chars: BTreeSet::new(),
+            language: None,
+            formats: Vec::new(),
+            scalable: None,
+            pixel_size: None,
+            style: None,
+        };
+        FontPropertyBuilder { property, invalid_css: None }
+    }
+
+    pub fn italic(mut self) -> FontPropertyBuilder {
+        self.property.slant = Slant::Italic;
+        self
+    }
+
+    pub fn oblique(mut self) -> FontPropertyBuilder {
+        self.property.slant = Slant::Oblique;
+        self
+    }
+
+    /// Sets the weight on the CSS / OpenType scale, e.g. 400 for regular and 700 for bold.
+    /// Values are clamped to the range 1 to 1000 allowed by CSS Fonts Level 4
+    pub fn weight(mut self, weight: u16) -> FontPropertyBuilder {
+        self.property.weight = weight.clamp(1, 1000);
+        self
+    }
+
+    pub fn thin(self) -> FontPropertyBuilder {
+        self.weight(100)
+    }
+
+    pub fn extra_light(self) -> FontPropertyBuilder {
+        self.weight(200)
+    }
+
+    pub fn light(self) -> FontPropertyBuilder {
+        self.weight(300)
+    }
+
+    pub fn regular(self) -> FontPropertyBuilder {
+        self.weight(400)
+    }
+
+    pub fn medium(self) -> FontPropertyBuilder {
+        self.weight(500)
+    }
+
+    pub fn semibold(self) -> FontPropertyBuilder {
+        self.weight(600)
+    }
+
+    pub fn bold(self) -> FontPropertyBuilder {
+        self.weight(700)
+    }
+
+    pub fn extra_bold(self) -> FontPropertyBuilder {
+        self.weight(800)
+    }
+
+    pub fn black(self) -> FontPropertyBuilder {
+        self.weight(900)
+    }
+
+    /// Sets the width of the face, e.g. to select condensed families. On Windows, where GDI
+    /// can't select faces by width, only faces of exactly this width are matched
+    pub fn stretch(mut self, stretch: Stretch) -> FontPropertyBuilder {
+        self.property.stretch = stretch;
+        self
+    }
+
+    pub fn condensed(self) -> FontPropertyBuilder {
+        self.stretch(Stretch::Condensed)
+    }
+
+    pub fn expanded(self) -> FontPropertyBuilder {
+        self.stretch(Stretch::Expanded)
+    }
+
+    pub fn monospace(mut self) -> FontPropertyBuilder {
+        self.property.spacing = Some(Spacing::Monospace);
+        self
+    }
+
+    pub fn spacing(mut self, spacing: Spacing) -> FontPropertyBuilder {
+        self.property.spacing = Some(spacing);
+        self
+    }
+
+    /// Requires matching faces to contain the character
+    pub fn covers_char(mut self, c: char) -> FontPropertyBuilder {
+        self.property.chars.insert(c);
+        self
+    }
+
+    /// Requires matching faces to contain every character of the string
+    pub fn covers_str(mut self, text: &str) -> FontPropertyBuilder {
+        self.property.chars.extend(text.chars());
+        self
+    }
+
+    /// Prefers faces supporting the language, given as BCP 47 tag like "ja" or "zh-Hant".
+    /// This selects the regional glyph variants of unified Han characters
+    pub fn language(mut self, tag: &str) -> FontPropertyBuilder {
+        self.property.language = Some(tag.to_string());
+        self
+    }
+
+    /// Restricts matching faces to the formats, e.g. `TrueType` and `Cff` for a renderer only
+    /// reading sfnt fonts. These are the formats of the font files, with the `woff` feature
+    /// WOFF and WOFF2 files are loaded as the font they wrap
+    pub fn formats(mut self, formats: &[FontFormat]) -> FontPropertyBuilder {
+        self.property.formats = formats.to_vec();
+        self
+    }
+
+    /// Restricts matching faces to scalable outline fonts with `true`, or to bitmap fonts with
+    /// `false`
+    pub fn scalable(mut self, scalable: bool) -> FontPropertyBuilder {
+        self.property.scalable = Some(scalable);
+        self
+    }
+
+    /// Prefers bitmap strikes of the pixel size, or else the closest one. Scalable fonts match
+    /// every size
+    pub fn pixel_size(mut self, size: f32) -> FontPropertyBuilder {
+        self.property.pixel_size = Some(size.to_bits());
+        self
+    }
+
+    /// Selects the face by its style name within the family, e.g. "SemiBold Condensed Italic",
+    /// as listed by `styles_of`. The weight, slant and stretch are set from the words of the
+    /// name, so that a close face matches where the backend can't select by style name, and
+    /// reset to normal where the name leaves them out
+    pub fn style(mut self, name: &str) -> FontPropertyBuilder {
+        let (weight, slant, stretch) = style_traits(name);
+        self.property.weight = weight;
+        self.property.slant = slant;
+        self.property.stretch = stretch;
+        self.property.style = Some(name.to_string());
+        self
+    }
+
+    /// Appends a family name. Later families are used as fallbacks for earlier ones
+    pub fn family(mut self, name: &str) -> FontPropertyBuilder {
+        if !name.is_empty() {
+            self.property.families.push(Family::Name(name.to_string()));
+        }
+        self
+    }
+
+    /// Appends a generic family, resolved to the families the system is configured to use for it
+    pub fn generic(mut self, generic: GenericFamily) -> FontPropertyBuilder {
+        self.property.families.push(Family::Generic(generic));
+        self
+    }
+
+    /// Appends the families of a CSS `font-family` list like `"Helvetica Neue", Arial, serif`.
+    /// Unquoted generic keywords like `serif` are taken as generic families
+    pub fn css_family(mut self, list: &str) -> FontPropertyBuilder {
+        match css::parse_family_list(list) {
+            Some(families) => self.property.families.extend(families),
+            None => self.invalid_css = Some(list.to_string()),
+        }
+        self
+    }
+
+    /// Applies a CSS `font` shorthand like `italic bold 12px/1.4 Georgia, serif`. As in CSS,
+    /// the slant, weight and stretch it leaves out are reset to normal. Its families are
+    /// appended, while the size and line height are ignored
+    pub fn css_font(mut self, font: &str) -> FontPropertyBuilder {
+        match css::parse_font(font) {
+            Some(shorthand) => {
+                self.property.slant = shorthand.slant;
+                self.property.weight = shorthand.weight;
+                self.property.stretch = shorthand.stretch;
+                self.property.families.extend(shorthand.families);
+            }
+            None => self.invalid_css = Some(font.to_string()),
+        }
+        self
+    }
+
+    /// Fails with `InvalidFamilyName` or `InvalidStyleName` if a family or the style name
+    /// contains a NUL byte, with `InvalidLanguage` if the language isn't made of alphanumeric
+    /// subtags, with `InvalidCss` if a CSS value can't be parsed and with `InvalidPixelSize` if
+    /// the pixel size isn't positive and finite
+    pub fn build(self) -> Result<FontProperty> {
+        if let Some(value) = self.invalid_css {
+            return Err(FontLoaderError::InvalidCss(value));
+        }
+        let invalid = self.property.families.iter().find(|family| family.name().contains('\0'));
+        if let Some(family) = invalid {
+            return Err(FontLoaderError::InvalidFamilyName(family.name().to_string()));
+        }
+        if let Some(style) = self.property.style() {
+            if style.contains('\0') {
+                return Err(FontLoaderError::InvalidStyleName(style.to_string()));
+            }
+        }
+        if let Some(ref tag) = self.property.language {
+            let valid = tag.split(&['-', '_'][..])
+                .all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+            if !valid {
+                return Err(FontLoaderError::InvalidLanguage(tag.clone()));
+            }
+        }
+        if let Some(size) = self.property.pixel_size() {
+            if !(size.is_finite() && size > 0.0) {
+                return Err(FontLoaderError::InvalidPixelSize(size));
+            }
+        }
+        Ok(self.property)
+    }
+}
+
+/// Style name keywords of each width. Keywords containing others come first
+static STRETCH_KEYWORDS: [(&str, Stretch); 8] = [
+    ("ultracondensed", Stretch::UltraCondensed),
+    ("extracondensed", Stretch::ExtraCondensed),
+    ("semicondensed", Stretch::SemiCondensed),
+    ("condensed", Stretch::Condensed),
+    ("semiexpanded", Stretch::SemiExpanded),
+    ("extraexpanded", Stretch::ExtraExpanded),
+    ("ultraexpanded", Stretch::UltraExpanded),
+    ("expanded", Stretch::Expanded),
+];
+
+static SLANT_KEYWORDS: [(&str, Slant); 2] = [("italic", Slant::Italic), ("oblique", Slant::Oblique)];
+
+/// Style name keywords of each weight. Keywords containing others come first
+static WEIGHT_KEYWORDS: [(&str, u16); 19] = [
+    ("extrablack", 950),
+    ("ultrablack", 950),
+    ("extralight", 200),
+    ("ultralight", 200),
+    ("semilight", 350),
+    ("demilight", 350),
+    ("extrabold", 800),
+    ("ultrabold", 800),
+    ("semibold", 600),
+    ("demibold", 600),
+    ("demi", 600),
+    ("hairline", 100),
+    ("thin", 100),
+    ("light", 300),
+    ("regular", 400),
+    ("medium", 500),
+    ("bold", 700),
+    ("heavy", 900),
+    ("black", 900),
+];
+
+/// Weight, slant and stretch named by a style name like "SemiBold Condensed Italic", normal
+/// for those it leaves out. Spaces and hyphens are ignored, so "Semi Bold" is "SemiBold"
+fn style_traits(name: &str) -> (u16, Slant, Stretch) {
+    let mut words: String = name.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase();
+    // Widths first, their prefixes would otherwise be taken for weights
+    let stretch = take_keyword(&mut words, &STRETCH_KEYWORDS).unwrap_or(Stretch::Normal);
+    let slant = take_keyword(&mut words, &SLANT_KEYWORDS).unwrap_or(Slant::Roman);
+    let weight = take_keyword(&mut words, &WEIGHT_KEYWORDS).unwrap_or(400);
+    (weight, slant, stretch)
+}
+
+/// Removes the first keyword found in the words and returns its value
+fn take_keyword<T: Copy>(words: &mut String, keywords: &[(&str, T)]) -> Option<T> {
+    let &(keyword, value) = keywords.iter().find(|&&(keyword, _)| words.contains(keyword))?;
+    *words = words.replacen(keyword, "", 1);
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn style_names() {
+        let styles = [
+            ("Regular", 400, Slant::Roman, Stretch::Normal),
+            ("", 400, Slant::Roman, Stretch::Normal),
+            ("Bold", 700, Slant::Roman, Stretch::Normal),
+            ("Bold Italic", 700, Slant::Italic, Stretch::Normal),
+            ("Oblique", 400, Slant::Oblique, Stretch::Normal),
+            ("Light Oblique", 300, Slant::Oblique, Stretch::Normal),
+            ("SemiCondensed Light", 300, Slant::Roman, Stretch::SemiCondensed),
+            ("Semi Bold", 600, Slant::Roman, Stretch::Normal),
+            ("Demi-Bold Italic", 600, Slant::Italic, Stretch::Normal),
+            ("Demi", 600, Slant::Roman, Stretch::Normal),
+            ("ExtraLight", 200, Slant::Roman, Stretch::Normal),
+            ("UltraBlack Italic", 950, Slant::Italic, Stretch::Normal),
+            ("Black", 900, Slant::Roman, Stretch::Normal),
+            ("Hairline", 100, Slant::Roman, Stretch::Normal),
+            ("Medium Condensed", 500, Slant::Roman, Stretch::Condensed),
+            ("Extra Condensed Bold", 700, Slant::Roman, Stretch::ExtraCondensed),
+            ("UltraCondensed Thin Italic", 100, Slant::Italic, Stretch::UltraCondensed),
+            ("Expanded Heavy", 900, Slant::Roman, Stretch::Expanded),
+            ("SemiExpanded", 400, Slant::Roman, Stretch::SemiExpanded),
+            ("ExtraExpanded Bold Oblique", 700, Slant::Oblique, Stretch::ExtraExpanded),
+            ("BOLD ITALIC", 700, Slant::Italic, Stretch::Normal),
+        ];
+        for &(name, weight, slant, stretch) in &styles {
+            assert_eq!(style_traits(name), (weight, slant, stretch), "{:?}", name);
+        }
+    }
+
+    /// A collection header whose first face starts at the offset, past padding
+    fn collection(offset: u32, face: &[u8]) -> Vec<u8> {
+        let mut data = b"ttcf\0\x01\0\0\0\0\0\x01".to_vec();
+        data.extend_from_slice(&offset.to_be_bytes());
+        data.resize(offset as usize, 0);
+        data.extend_from_slice(face);
+        data
+    }
+
+    #[test]
+    fn format_detection() {
+        let formats = [
+            (b"\0\x01\0\0\0\x0a".to_vec(), Some(FontFormat::TrueType)),
+            (b"true\0\x0a".to_vec(), Some(FontFormat::TrueType)),
+            (b"OTTO\0\x0a".to_vec(), Some(FontFormat::Cff)),
+            (collection(16, b"\0\x01\0\0"), Some(FontFormat::TrueType)),
+            (collection(16, b"true"), Some(FontFormat::TrueType)),
+            // Past the bytes `detect_file` reads first
+            (collection(100, b"OTTO"), Some(FontFormat::Cff)),
+            (b"wOFF\0\x01\0\0".to_vec(), Some(FontFormat::Woff)),
+            (b"wOF2OTTO".to_vec(), Some(FontFormat::Woff2)),
+            (b"\x01fcp\x0e\0\0\0".to_vec(), Some(FontFormat::Pcf)),
+            (b"STARTFONT 2.1\n".to_vec(), Some(FontFormat::Bdf)),
+            (b"%!PS-AdobeFont-1.0: Utopia\n".to_vec(), Some(FontFormat::Type1)),
+            (b"%!FontType1-1.0: Utopia\n".to_vec(), Some(FontFormat::Type1)),
+            (b"\x80\x01\x10\0\0\0%!PS".to_vec(), Some(FontFormat::Type1)),
+            // Truncated
+            (Vec::new(), None),
+            (b"OTT".to_vec(), None),
+            (b"ttcf".to_vec(), None),
+            (b"ttcf\0\x01\0\0\0\0\0\x01\0\0".to_vec(), None),
+            (collection(16, b"OT"), None),
+            (b"STARTFON".to_vec(), None),
+            (b"%!PS-Adobe".to_vec(), None),
+            // Unknown
+            (b"abcd\0\x01\0\0".to_vec(), None),
+            (collection(16, b"wOFF"), None),
+            (b"%!PS-Adobe-3.0 EPSF-3.0\n".to_vec(), None),
+        ];
+        let path = env::temp_dir().join(format!("font-loader-format-{}", ::std::process::id()));
+        for (data, format) in &formats {
+            assert_eq!(FontFormat::detect(data), *format, "{:?}", data);
+            fs::write(&path, data).unwrap();
+            assert_eq!(FontFormat::detect_file(&path), *format, "{:?}", data);
+        }
+        fs::remove_file(&path).unwrap();
+        assert_eq!(FontFormat::detect_file(&path), None);
+    }
+}
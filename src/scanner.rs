@@ -0,0 +1,180 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+/// Font backend scanning the XDG font directories itself, for systems without fontconfig
+pub mod system_fonts {
+    use database::FontDatabase;
+    use error::Result;
+    pub use handle::FontHandle;
+    pub use info::{FontInfo, StyleInfo};
+    pub use property::{Family, FontProperty, FontPropertyBuilder, GenericFamily};
+    pub use property::{FontFormat, Slant, Spacing, Stretch};
+
+    use libc::c_int;
+
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+
+    /// The installed fonts and the application fonts registered on top of them
+    struct State {
+        fonts: Arc<FontDatabase>,
+        app_files: Vec<PathBuf>,
+        app_dirs: Vec<PathBuf>,
+    }
+
+    impl State {
+        fn load(app_files: Vec<PathBuf>, app_dirs: Vec<PathBuf>) -> State {
+            let mut fonts = FontDatabase::new();
+            fonts.load_system_fonts();
+            // Application fonts removed in the meantime are dropped
+            for file in &app_files {
+                let _ = fonts.load_font_file(file);
+            }
+            for dir in &app_dirs {
+                let _ = fonts.load_fonts_dir(dir);
+            }
+            State { fonts: Arc::new(fonts), app_files, app_dirs }
+        }
+    }
+
+    /// Scanned on first use
+    static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+    fn with_state<T, F: FnOnce(&mut State) -> T>(f: F) -> T {
+        let mut state = STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(state.get_or_insert_with(|| State::load(Vec::new(), Vec::new())))
+    }
+
+    fn database() -> Arc<FontDatabase> {
+        with_state(|state| state.fonts.clone())
+    }
+
+    /// Register a font file for this process, after which it is matched like an installed font.
+    /// Fails with `UnsupportedFormat` if it isn't a TrueType or OpenType font
+    pub fn add_font_file<P: AsRef<Path>>(path: P) -> Result<()> {
+        let path = path.as_ref();
+        with_state(|state| {
+            Arc::make_mut(&mut state.fonts).load_font_file(path)?;
+            state.app_files.push(path.to_path_buf());
+            Ok(())
+        })
+    }
+
+    /// Register the fonts in a directory and its subdirectories for this process
+    pub fn add_font_dir<P: AsRef<Path>>(path: P) -> Result<()> {
+        let path = path.as_ref();
+        with_state(|state| {
+            Arc::make_mut(&mut state.fonts).load_fonts_dir(path)?;
+            state.app_dirs.push(path.to_path_buf());
+            Ok(())
+        })
+    }
+
+    /// Walk the font directories again. Returns whether the installed fonts changed.
+    /// Registered application fonts are kept
+    pub fn rescan() -> Result<bool> {
+        with_state(|state| {
+            let rescanned = State::load(state.app_files.clone(), state.app_dirs.clone());
+            let changed = !rescanned.fonts.same_faces(&state.fonts);
+            *state = rescanned;
+            Ok(changed)
+        })
+    }
+
+    /// Get the binary data and index of a specific font
+    /// Note that only TrueType and OpenType fonts are supported
+    pub fn get(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        database().get(property)
+    }
+
+    /// Resolve the file and face index of a specific font without reading it
+    pub fn locate(property: &FontProperty) -> Result<FontHandle> {
+        database().locate(property)
+    }
+
+    /// Find the best installed face containing the character, preferring faces close to the
+    /// requested properties
+    pub fn fallback_for(c: char, property: &FontProperty) -> Result<FontHandle> {
+        database().fallback_for(c, property)
+    }
+
+    /// Get all faces ordered from best to worst match, as a fallback list for shaping text.
+    /// With `trim`, faces which don't contribute characters missing from the faces before them
+    /// are left out
+    pub fn match_sorted(property: &FontProperty, trim: bool) -> Result<Vec<FontHandle>> {
+        database().match_sorted(property, trim)
+    }
+
+    /// Query the names of all fonts installed in the system
+    /// Note that only TrueType and OpenType fonts are supported
+    pub fn query_all() -> Result<Vec<String>> {
+        database().query_all()
+    }
+
+    /// Query the names of specifc fonts installed in the system
+    /// Note that only TrueType and OpenType fonts are supported
+    pub fn query_specific(property: &FontProperty) -> Result<Vec<String>> {
+        database().query_specific(property)
+    }
+
+    /// Query every face of the fonts matching the property's families and spacing.
+    /// Weight, slant and stretch are ignored, so that all styles of a family are returned
+    pub fn query_faces(property: &FontProperty) -> Result<Vec<FontInfo>> {
+        database().query_faces(property)
+    }
+
+    /// The styles the installed family provides, e.g. to let users pick one by name for
+    /// `FontPropertyBuilder::style`
+    pub fn styles_of(family: &str) -> Result<Vec<StyleInfo>> {
+        database().styles_of(family)
+    }
+
+    /// Get the binary data and index of the first family of the property which is installed,
+    /// as CSS walks a font stack. Unlike `get`, no other family is substituted, so this fails
+    /// with `NoMatch` if none of them is installed
+    pub fn get_first_available(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        database().get_first_available(property)
+    }
+
+    /// Resolve the file and face index of the face CSS Fonts Level 4 font matching selects,
+    /// among the faces of the first family of the property which is installed. Unlike
+    /// `locate`, the result doesn't depend on the platform
+    pub fn locate_css(property: &FontProperty) -> Result<FontHandle> {
+        database().locate_css(property)
+    }
+
+    /// Get the binary data and index of the installed face with the PostScript name, such as
+    /// `Roboto-BoldItalic`, as documents reference fonts. No other face is substituted, names
+    /// are compared ignoring ASCII case
+    pub fn get_by_postscript_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        database().get_by_postscript_name(name)
+    }
+
+    /// Get the binary data and index of the installed face with the full name, such as
+    /// `Roboto Bold Italic`. No other face is substituted, names are compared ignoring ASCII
+    /// case
+    pub fn get_by_full_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        database().get_by_full_name(name)
+    }
+
+    /// Resolve a generic family to the installed families commonly used for it, most
+    /// preferred first
+    pub fn resolve_generic(generic: GenericFamily) -> Vec<String> {
+        database().resolve_generic(generic)
+    }
+}
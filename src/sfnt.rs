@@ -0,0 +1,923 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Minimal reader for the sfnt tables needed to describe a face: `name`, `OS/2`, `head`,
+//! `post` and `cmap`. Faces of collections can also be extracted into standalone fonts
+
+use error::{FontLoaderError, Result};
+use property::{FontFormat, Slant, Spacing, Stretch};
+
+use libc::c_int;
+
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+const TTCF: u32 = 0x7474_6366; // "ttcf"
+const TRUETYPE: u32 = 0x0001_0000;
+const TRUE: u32 = 0x7472_7565; // "true"
+const OTTO: u32 = 0x4F54_544F; // "OTTO"
+
+const HEAD: u32 = 0x6865_6164; // "head"
+const DSIG: u32 = 0x4453_4947; // "DSIG"
+const CFF: u32 = 0x4346_4620; // "CFF "
+const CFF2: u32 = 0x4346_4632; // "CFF2"
+/// The whole font sums up to this value, see `checkSumAdjustment` of the `head` table
+const CHECKSUM_MAGIC: u32 = 0xB1B0_AFBA;
+
+const NAME_FAMILY: u16 = 1;
+const NAME_STYLE: u16 = 2;
+const NAME_FULL_NAME: u16 = 4;
+const NAME_POSTSCRIPT_NAME: u16 = 6;
+const NAME_TYPOGRAPHIC_FAMILY: u16 = 16;
+const NAME_TYPOGRAPHIC_STYLE: u16 = 17;
+
+/// A face described by its sfnt tables
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Face {
+    pub family: String,
+    pub style: String,
+    pub full_name: String,
+    pub postscript_name: String,
+    pub weight: u16,
+    pub slant: Slant,
+    pub stretch: Stretch,
+    pub spacing: Spacing,
+    pub index: u32,
+    /// `Cff` for PostScript outlines, `TrueType` otherwise. Faces decoded from WOFF and WOFF2
+    /// have the format of the wrapping file
+    pub format: FontFormat,
+    pub coverage: Coverage,
+    /// `ulCodePageRange1` of the OS/2 table, 0 if absent
+    pub code_pages: u32,
+}
+
+/// Set of characters as sorted, non-overlapping, inclusive ranges
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Coverage {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Coverage {
+    pub fn contains(&self, c: char) -> bool {
+        let c = c as u32;
+        match self.ranges.binary_search_by(|&(start, _)| start.cmp(&c)) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(pos) => c <= self.ranges[pos - 1].1,
+        }
+    }
+
+    pub fn is_subset(&self, other: &Coverage) -> bool {
+        self.ranges.iter().all(|&(start, end)| {
+            other.ranges.iter().any(|&(other_start, other_end)| other_start <= start && end <= other_end)
+        })
+    }
+
+    pub fn union(&mut self, other: &Coverage) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.normalize();
+    }
+
+    fn push(&mut self, start: u32, end: u32) {
+        match self.ranges.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) && start >= last.0 => {
+                last.1 = cmp::max(last.1, end);
+                return;
+            }
+            _ => {}
+        }
+        self.ranges.push((start, end));
+    }
+
+    fn normalize(&mut self) {
+        let mut ranges = ::std::mem::take(&mut self.ranges);
+        ranges.sort();
+        for (start, end) in ranges {
+            self.push(start, end);
+        }
+    }
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from(bytes[0]) << 24 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 8 |
+         u32::from(bytes[3]))
+}
+
+/// Offsets of the table directories of all faces in a font file or collection
+pub(crate) fn face_offsets(data: &[u8]) -> Vec<usize> {
+    match read_u32(data, 0) {
+        Some(TTCF) => {
+            // The count is untrusted, a collection whose offsets don't fit in the data is invalid
+            let count = read_u32(data, 8).unwrap_or(0) as usize;
+            if count > data.len().saturating_sub(12) / 4 {
+                return Vec::new();
+            }
+            (0..count)
+                .filter_map(|i| read_u32(data, 12 + 4 * i))
+                .map(|offset| offset as usize)
+                .collect()
+        }
+        Some(TRUETYPE) | Some(TRUE) | Some(OTTO) => vec![0],
+        _ => Vec::new(),
+    }
+}
+
+/// Number of faces in a font file or collection, zero if the data isn't a TrueType or OpenType
+/// font
+pub fn face_count(data: &[u8]) -> usize {
+    face_offsets(data).len()
+}
+
+/// Extract a face of a TrueType or OpenType collection as a standalone font
+///
+/// The face's tables are copied into a new file with a table directory of its own, for
+/// consumers which only accept single fonts, like PDF embedding. Fonts which aren't collections
+/// are returned unchanged for index 0. The upper 16 bits of the index, which fontconfig uses for
+/// named instances of variable fonts, are ignored.
+pub fn extract_face(data: &[u8], index: c_int) -> Result<Vec<u8>> {
+    let offsets = face_offsets(data);
+    if offsets.is_empty() {
+        return Err(FontLoaderError::UnsupportedFormat);
+    }
+    let face_offset = *offsets.get((index & 0xFFFF) as usize)
+        .ok_or(FontLoaderError::InvalidFaceIndex(index))?;
+    if read_u32(data, 0) != Some(TTCF) {
+        return Ok(data.to_vec());
+    }
+    rebuild_face(data, face_offset).ok_or(FontLoaderError::UnsupportedFormat)
+}
+
+/// Copies the tables of the face into a new file
+fn rebuild_face(data: &[u8], face_offset: usize) -> Option<Vec<u8>> {
+    let version = read_u32(data, face_offset)?;
+    let count = read_u16(data, face_offset + 4)? as usize;
+    let mut tables: Vec<(u32, &[u8])> = Vec::with_capacity(count);
+    for i in 0..count {
+        let record = face_offset + 12 + 16 * i;
+        let tag = read_u32(data, record)?;
+        // A signature would be invalid for the rebuilt file
+        if tag == DSIG {
+            continue;
+        }
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        tables.push((tag, data.get(offset..offset.checked_add(length)?)?));
+    }
+    Some(build_sfnt(version, tables))
+}
+
+/// Lays out the tables as a font file, with the table directory, checksums and the `head`
+/// table's `checkSumAdjustment` computed for the new file
+pub(crate) fn build_sfnt(version: u32, mut tables: Vec<(u32, &[u8])>) -> Vec<u8> {
+    tables.sort_by_key(|&(tag, _)| tag);
+    let mut offset = 12 + 16 * tables.len();
+    let mut records = Vec::with_capacity(tables.len());
+    for &(tag, table) in &tables {
+        records.push((tag, table_checksum(tag, table), offset, table.len()));
+        offset += padded(table.len());
+    }
+    let mut font = Vec::with_capacity(offset);
+    push_directory(&mut font, version, &records);
+    let mut head = None;
+    for &(tag, table) in &tables {
+        if tag == HEAD && table.len() >= 12 {
+            head = Some(font.len());
+        }
+        font.extend_from_slice(table);
+        font.resize(padded(font.len()), 0);
+    }
+    if let Some(head) = head {
+        font[head + 8..head + 12].copy_from_slice(&[0; 4]);
+        let adjustment = CHECKSUM_MAGIC.wrapping_sub(checksum(&font));
+        font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+/// Appends a table directory with records of tag, checksum, offset and length, which have to
+/// be sorted by tag
+pub(crate) fn push_directory(font: &mut Vec<u8>, version: u32, records: &[(u32, u32, usize, usize)]) {
+    let count = records.len();
+    let entry_selector = (0..16).rev().find(|&bits| 1 << bits <= count).unwrap_or(0);
+    let search_range = 16 << entry_selector;
+    font.extend_from_slice(&version.to_be_bytes());
+    for &value in &[count, search_range, entry_selector, (16 * count).saturating_sub(search_range)] {
+        font.extend_from_slice(&(value as u16).to_be_bytes());
+    }
+    for &(tag, checksum, offset, length) in records {
+        for &value in &[tag, checksum, offset as u32, length as u32] {
+            font.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Checksum of a table, for `head` with its `checkSumAdjustment` taken as zero
+pub(crate) fn table_checksum(tag: u32, table: &[u8]) -> u32 {
+    let sum = checksum(table);
+    if tag == HEAD && table.len() >= 12 {
+        sum.wrapping_sub(read_u32(table, 8).unwrap_or(0))
+    } else {
+        sum
+    }
+}
+
+/// Sum of the big endian 32 bit words, with the data padded to whole words
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Length of a table padded to whole 32 bit words
+pub(crate) fn padded(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+/// Table of the face whose table directory starts at `face_offset`
+pub(crate) fn table<'a>(data: &'a [u8], face_offset: usize, tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let tag = read_u32(tag, 0)?;
+    let count = read_u16(data, face_offset + 4)? as usize;
+    for i in 0..count {
+        let record = face_offset + 12 + 16 * i;
+        if read_u32(data, record)? == tag {
+            let offset = read_u32(data, record + 8)? as usize;
+            let length = read_u32(data, record + 12)? as usize;
+            return data.get(offset..offset.checked_add(length)?);
+        }
+    }
+    None
+}
+
+/// Describes every face of a font file or collection. Faces without usable names are skipped
+pub(crate) fn parse_faces(data: &[u8]) -> Vec<Face> {
+    face_offsets(data)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, offset)| parse_face(data, offset, index as u32))
+        .collect()
+}
+
+/// Reads the faces of a font file or collection with only the listed tables, seeking past the
+/// others instead of loading the whole file. Outline tables are kept empty, their presence tells
+/// the format apart. Faces whose table directory can't be read are skipped
+pub(crate) fn read_faces<R: Read + Seek>(reader: &mut R, tags: &[&[u8; 4]]) -> io::Result<Vec<Face>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let header = read_at(reader, 0, 12)?;
+    let offsets: Vec<u64> = match read_u32(&header, 0) {
+        Some(TTCF) => {
+            // The count is untrusted, as in `face_offsets`
+            let count = read_u32(&header, 8).unwrap_or(0) as u64;
+            if count > len.saturating_sub(12) / 4 {
+                return Ok(Vec::new());
+            }
+            read_at(reader, 12, 4 * count as usize)?
+                .chunks_exact(4)
+                .filter_map(|offset| read_u32(offset, 0))
+                .map(u64::from)
+                .collect()
+        }
+        Some(TRUETYPE) | Some(TRUE) | Some(OTTO) => vec![0],
+        _ => return Ok(Vec::new()),
+    };
+    let tags: Vec<u32> = tags.iter().filter_map(|tag| read_u32(&tag[..], 0)).collect();
+    let mut faces = Vec::new();
+    for (index, offset) in offsets.into_iter().enumerate() {
+        if let Some(face) = read_face(reader, offset, len, &tags, index as u32)? {
+            faces.push(face);
+        }
+    }
+    Ok(faces)
+}
+
+/// Reads the listed tables of the face whose table directory starts at `offset` into a
+/// standalone font and parses it
+fn read_face<R: Read + Seek>(reader: &mut R, offset: u64, len: u64, tags: &[u32], index: u32)
+                             -> io::Result<Option<Face>> {
+    let header = read_at(reader, offset, 12)?;
+    let (version, count) = match (read_u32(&header, 0), read_u16(&header, 4)) {
+        (Some(version), Some(count)) => (version, count as usize),
+        _ => return Ok(None),
+    };
+    let mut tables: Vec<(u32, Vec<u8>)> = Vec::new();
+    for record in read_at(reader, offset + 12, 16 * count)?.chunks_exact(16) {
+        let (tag, start, length) = match (read_u32(record, 0), read_u32(record, 8), read_u32(record, 12)) {
+            (Some(tag), Some(start), Some(length)) => (tag, u64::from(start), u64::from(length)),
+            _ => continue,
+        };
+        if tag == CFF || tag == CFF2 {
+            tables.push((tag, Vec::new()));
+        } else if tags.contains(&tag) && start + length <= len {
+            tables.push((tag, read_at(reader, start, length as usize)?));
+        }
+    }
+    let font = build_sfnt(version, tables.iter().map(|&(tag, ref table)| (tag, &table[..])).collect());
+    Ok(parse_face(&font, 0, index))
+}
+
+/// Reads up to `length` bytes at the offset
+fn read_at<R: Read + Seek>(reader: &mut R, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::new();
+    reader.by_ref().take(length as u64).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn parse_face(data: &[u8], offset: usize, index: u32) -> Option<Face> {
+    let name = table(data, offset, b"name")?;
+    let family = find_name(name, NAME_TYPOGRAPHIC_FAMILY).or_else(|| find_name(name, NAME_FAMILY))?;
+    let style = find_name(name, NAME_TYPOGRAPHIC_STYLE)
+        .or_else(|| find_name(name, NAME_STYLE))
+        .unwrap_or_else(|| "Regular".to_string());
+    let full_name = find_name(name, NAME_FULL_NAME).unwrap_or_else(|| format!("{} {}", family, style));
+    let postscript_name = find_name(name, NAME_POSTSCRIPT_NAME).unwrap_or_default();
+
+    let mut weight = 400;
+    let mut stretch = Stretch::Normal;
+    let mut slant = Slant::Roman;
+    let mut code_pages = 0;
+    if let Some(os2) = table(data, offset, b"OS/2") {
+        weight = read_u16(os2, 4).map_or(400, |weight| weight.clamp(1, 1000));
+        stretch = read_u16(os2, 6).map_or(Stretch::Normal, stretch_from_class);
+        let selection = read_u16(os2, 62).unwrap_or(0);
+        if selection & (1 << 9) != 0 {
+            slant = Slant::Oblique;
+        } else if selection & 1 != 0 {
+            slant = Slant::Italic;
+        }
+        if read_u16(os2, 0).unwrap_or(0) >= 1 {
+            code_pages = read_u32(os2, 78).unwrap_or(0);
+        }
+    } else if let Some(head) = table(data, offset, b"head") {
+        let mac_style = read_u16(head, 44).unwrap_or(0);
+        if mac_style & 1 != 0 {
+            weight = 700;
+        }
+        if mac_style & 2 != 0 {
+            slant = Slant::Italic;
+        }
+    }
+
+    // Many oblique faces only set the italic bit, fontconfig tells them apart by the style name
+    if slant == Slant::Italic && style.to_lowercase().contains("oblique") {
+        slant = Slant::Oblique;
+    }
+
+    let monospace = table(data, offset, b"post")
+        .and_then(|post| read_u32(post, 12))
+        .is_some_and(|fixed_pitch| fixed_pitch != 0);
+    let format = if table(data, offset, b"CFF ").is_some() || table(data, offset, b"CFF2").is_some() {
+        FontFormat::Cff
+    } else {
+        FontFormat::TrueType
+    };
+    let coverage = table(data, offset, b"cmap").map(parse_cmap).unwrap_or_default();
+
+    Some(Face {
+        family,
+        style,
+        full_name,
+        postscript_name,
+        weight,
+        slant,
+        stretch,
+        spacing: if monospace { Spacing::Monospace } else { Spacing::Proportional },
+        index,
+        format,
+        coverage,
+        code_pages,
+    })
+}
+
+/// The stretch of an OS/2 `usWidthClass`
+pub(crate) fn stretch_from_class(class: u16) -> Stretch {
+    match class {
+        1 => Stretch::UltraCondensed,
+        2 => Stretch::ExtraCondensed,
+        3 => Stretch::Condensed,
+        4 => Stretch::SemiCondensed,
+        6 => Stretch::SemiExpanded,
+        7 => Stretch::Expanded,
+        8 => Stretch::ExtraExpanded,
+        9 => Stretch::UltraExpanded,
+        _ => Stretch::Normal,
+    }
+}
+
+/// All names with the ID, preferring US English Windows names over other languages and
+/// platforms
+pub(crate) fn find_names(name: &[u8], name_id: u16) -> Vec<String> {
+    let count = read_u16(name, 2).unwrap_or(0) as usize;
+    let storage = read_u16(name, 4).unwrap_or(0) as usize;
+    let mut names: Vec<(u8, String)> = Vec::new();
+    for i in 0..count {
+        let record = 6 + 12 * i;
+        let (platform, encoding, language, id, length, offset) = match (
+            read_u16(name, record),
+            read_u16(name, record + 2),
+            read_u16(name, record + 4),
+            read_u16(name, record + 6),
+            read_u16(name, record + 8),
+            read_u16(name, record + 10),
+        ) {
+            (Some(p), Some(e), Some(l), Some(id), Some(len), Some(off)) => (p, e, l, id, len, off),
+            _ => break,
+        };
+        if id != name_id {
+            continue;
+        }
+        let start = storage + offset as usize;
+        let bytes = match name.get(start..start + length as usize) {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let (rank, decoded) = match (platform, encoding) {
+            (3, 1) | (3, 10) | (0, _) => {
+                let rank = if platform == 3 && language == 0x0409 { 0 } else { 1 };
+                (rank, decode_utf16(bytes))
+            }
+            (1, 0) => (2, bytes.iter().map(|&b| mac_roman(b)).collect()),
+            _ => continue,
+        };
+        if !decoded.is_empty() {
+            names.push((rank, decoded));
+        }
+    }
+    names.sort_by_key(|&(rank, _)| rank);
+    let mut unique: Vec<String> = Vec::new();
+    for (_, name) in names {
+        if !unique.contains(&name) {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+pub(crate) fn find_name(name: &[u8], name_id: u16) -> Option<String> {
+    find_names(name, name_id).into_iter().next()
+}
+
+fn decode_utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| u16::from(pair[0]) << 8 | u16::from(pair[1]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Mac Roman decoding, exact for ASCII which covers nearly all font names
+fn mac_roman(byte: u8) -> char {
+    if byte < 0x80 {
+        byte as char
+    } else {
+        '\u{FFFD}'
+    }
+}
+
+/// Characters mapped to a glyph by the best Unicode `cmap` subtable
+fn parse_cmap(cmap: &[u8]) -> Coverage {
+    let count = read_u16(cmap, 2).unwrap_or(0) as usize;
+    let mut best: Option<(u8, usize)> = None;
+    for i in 0..count {
+        let record = 4 + 8 * i;
+        let (platform, encoding, offset) = match (read_u16(cmap, record), read_u16(cmap, record + 2),
+                                                  read_u32(cmap, record + 4)) {
+            (Some(p), Some(e), Some(off)) => (p, e, off as usize),
+            _ => break,
+        };
+        let format = read_u16(cmap, offset).unwrap_or(0);
+        let rank = match (platform, encoding, format) {
+            (3, 10, 12) | (0, 4, 12) | (0, 6, 12) => 0,
+            (3, 1, 4) | (0, 3, 4) | (0, 1, 4) | (0, 0, 4) => 1,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, _)| rank < best_rank) {
+            best = Some((rank, offset));
+        }
+    }
+    let mut coverage = Coverage::default();
+    if let Some((_, offset)) = best {
+        let subtable = &cmap[offset..];
+        match read_u16(subtable, 0) {
+            Some(4) => parse_format4(subtable, &mut coverage),
+            Some(12) => parse_format12(subtable, &mut coverage),
+            _ => {}
+        }
+    }
+    coverage.normalize();
+    coverage
+}
+
+fn parse_format4(subtable: &[u8], coverage: &mut Coverage) {
+    let seg_count = read_u16(subtable, 6).unwrap_or(0) as usize / 2;
+    let ends = 14;
+    let starts = ends + 2 * seg_count + 2;
+    let deltas = starts + 2 * seg_count;
+    let range_offsets = deltas + 2 * seg_count;
+    for seg in 0..seg_count {
+        let (end, start, delta, range_offset) = match (
+            read_u16(subtable, ends + 2 * seg),
+            read_u16(subtable, starts + 2 * seg),
+            read_u16(subtable, deltas + 2 * seg),
+            read_u16(subtable, range_offsets + 2 * seg),
+        ) {
+            (Some(e), Some(s), Some(d), Some(r)) => (e, s, d, r),
+            _ => return,
+        };
+        if start > end || start == 0xFFFF {
+            continue;
+        }
+        if range_offset == 0 {
+            for c in u32::from(start)..=u32::from(end) {
+                if (c as u16).wrapping_add(delta) != 0 {
+                    coverage.push(c, c);
+                }
+            }
+            continue;
+        }
+        for c in start..=end {
+            let glyph_offset = range_offsets + 2 * seg + range_offset as usize + 2 * (c - start) as usize;
+            let glyph = match read_u16(subtable, glyph_offset) {
+                Some(0) | None => continue,
+                Some(glyph) => glyph.wrapping_add(delta),
+            };
+            if glyph != 0 {
+                coverage.push(u32::from(c), u32::from(c));
+            }
+        }
+    }
+}
+
+fn parse_format12(subtable: &[u8], coverage: &mut Coverage) {
+    let groups = read_u32(subtable, 12).unwrap_or(0) as usize;
+    for group in 0..groups {
+        let record = 16 + 12 * group;
+        let (start, end, glyph) = match (read_u32(subtable, record), read_u32(subtable, record + 4),
+                                         read_u32(subtable, record + 8)) {
+            (Some(s), Some(e), Some(g)) => (s, e, g),
+            _ => return,
+        };
+        if start > end || end > 0x10_FFFF {
+            continue;
+        }
+        // Only the first character of a group starting at glyph 0 maps to .notdef
+        let start = if glyph == 0 { start + 1 } else { start };
+        if start <= end {
+            coverage.push(start, end);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn tag(tag: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*tag)
+    }
+
+    /// A `name` table with US English Windows names for the family, style, full and PostScript
+    /// names
+    pub(crate) fn name_table(family: &str, style: &str) -> Vec<u8> {
+        let full_name = format!("{} {}", family, style);
+        let postscript_name = format!("{}-{}", family, style).replace(' ', "");
+        let names = [(NAME_FAMILY, family), (NAME_STYLE, style), (NAME_FULL_NAME, &full_name[..]),
+                     (NAME_POSTSCRIPT_NAME, &postscript_name[..])];
+        let mut records = Vec::new();
+        let mut storage = Vec::new();
+        for &(id, name) in &names {
+            let encoded: Vec<u8> = name.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect();
+            for &value in &[3, 1, 0x0409, id, encoded.len() as u16, storage.len() as u16] {
+                records.extend_from_slice(&value.to_be_bytes());
+            }
+            storage.extend_from_slice(&encoded);
+        }
+        let mut table = Vec::new();
+        for &value in &[0, names.len() as u16, 6 + records.len() as u16] {
+            table.extend_from_slice(&value.to_be_bytes());
+        }
+        table.extend_from_slice(&records);
+        table.extend_from_slice(&storage);
+        table
+    }
+
+    /// A version 1 `OS/2` table with the weight, width class, `fsSelection` and
+    /// `ulCodePageRange1`
+    pub(crate) fn os2_table(weight: u16, width_class: u16, selection: u16, code_pages: u32) -> Vec<u8> {
+        let mut table = vec![0; 86];
+        table[0..2].copy_from_slice(&1u16.to_be_bytes());
+        table[4..6].copy_from_slice(&weight.to_be_bytes());
+        table[6..8].copy_from_slice(&width_class.to_be_bytes());
+        table[62..64].copy_from_slice(&selection.to_be_bytes());
+        table[78..82].copy_from_slice(&code_pages.to_be_bytes());
+        table
+    }
+
+    /// A `post` table, only filled in with `isFixedPitch`
+    pub(crate) fn post_table(monospace: bool) -> Vec<u8> {
+        let mut table = vec![0; 32];
+        table[0..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+        table[12..16].copy_from_slice(&u32::from(monospace).to_be_bytes());
+        table
+    }
+
+    /// A `cmap` table with a Windows format 4 subtable mapping the inclusive ranges by delta,
+    /// or a format 12 subtable if any range is beyond the Basic Multilingual Plane
+    pub(crate) fn cmap_table(ranges: &[(u32, u32)]) -> Vec<u8> {
+        if ranges.iter().any(|&(_, end)| end > 0xFFFF) {
+            cmap(&[(3, 10, format12(ranges))])
+        } else {
+            cmap(&[(3, 1, format4(ranges))])
+        }
+    }
+
+    /// A `cmap` table with the subtables of each platform and encoding
+    fn cmap(subtables: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut table = Vec::new();
+        table.extend_from_slice(&0u16.to_be_bytes());
+        table.extend_from_slice(&(subtables.len() as u16).to_be_bytes());
+        let mut offset = 4 + 8 * subtables.len();
+        for &(platform, encoding, ref subtable) in subtables {
+            table.extend_from_slice(&platform.to_be_bytes());
+            table.extend_from_slice(&encoding.to_be_bytes());
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            offset += subtable.len();
+        }
+        for (_, _, subtable) in subtables {
+            table.extend_from_slice(subtable);
+        }
+        table
+    }
+
+    fn format4(ranges: &[(u32, u32)]) -> Vec<u8> {
+        let mut segments: Vec<(u16, u16, u16)> = ranges.iter()
+            .map(|&(start, end)| (start as u16, end as u16, 1))
+            .collect();
+        segments.push((0xFFFF, 0xFFFF, 1));
+        let seg_count = segments.len() as u16;
+        let mut subtable = Vec::new();
+        for &value in &[4, 16 + 8 * seg_count, 0, 2 * seg_count, 0, 0, 0] {
+            subtable.extend_from_slice(&value.to_be_bytes());
+        }
+        for &(_, end, _) in &segments {
+            subtable.extend_from_slice(&end.to_be_bytes());
+        }
+        subtable.extend_from_slice(&[0, 0]);
+        for &(start, _, _) in &segments {
+            subtable.extend_from_slice(&start.to_be_bytes());
+        }
+        for &(_, _, delta) in &segments {
+            subtable.extend_from_slice(&delta.to_be_bytes());
+        }
+        subtable.resize(subtable.len() + 2 * segments.len(), 0);
+        subtable
+    }
+
+    fn format12(ranges: &[(u32, u32)]) -> Vec<u8> {
+        let mut subtable = Vec::new();
+        subtable.extend_from_slice(&12u16.to_be_bytes());
+        subtable.extend_from_slice(&0u16.to_be_bytes());
+        for &value in &[16 + 12 * ranges.len() as u32, 0, ranges.len() as u32] {
+            subtable.extend_from_slice(&value.to_be_bytes());
+        }
+        for &(start, end) in ranges {
+            for &value in &[start, end, 1] {
+                subtable.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        subtable
+    }
+
+    fn ttcf_header(count: u32, offsets: &[u32]) -> Vec<u8> {
+        let mut data = b"ttcf\x00\x01\x00\x00".to_vec();
+        data.extend_from_slice(&count.to_be_bytes());
+        for offset in offsets {
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn collection_offsets() {
+        assert_eq!(face_offsets(&ttcf_header(2, &[20, 40])), vec![20, 40]);
+        assert_eq!(face_offsets(&ttcf_header(0, &[])), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn oversized_collection_count() {
+        let data = ttcf_header(0xFFFF_FFFF, &[20, 40]);
+        assert_eq!(face_count(&data), 0);
+        assert!(extract_face(&data, 0).is_err());
+    }
+
+    #[test]
+    fn truncated_collection_header() {
+        assert_eq!(face_count(&ttcf_header(3, &[20, 40])), 0);
+        assert_eq!(face_count(b"ttcf\x00\x01"), 0);
+    }
+
+    /// A collection of the faces, given as tables. Tables are laid out unpadded in reverse, so
+    /// that extraction has to sort and align them
+    fn collection(faces: &[Vec<(u32, Vec<u8>)>]) -> Vec<u8> {
+        let directories: usize = faces.iter().map(|tables| 12 + 16 * tables.len()).sum();
+        let mut offset = 12 + 4 * faces.len() + directories;
+        let mut data = Vec::new();
+        let mut header = b"ttcf\x00\x01\x00\x00".to_vec();
+        header.extend_from_slice(&(faces.len() as u32).to_be_bytes());
+        let mut directory_offset = 12 + 4 * faces.len();
+        let mut directory = Vec::new();
+        for tables in faces {
+            header.extend_from_slice(&(directory_offset as u32).to_be_bytes());
+            directory_offset += 12 + 16 * tables.len();
+            let mut records = Vec::new();
+            for &(tag, ref table) in tables.iter().rev() {
+                records.push((tag, table_checksum(tag, table), offset, table.len()));
+                data.extend_from_slice(table);
+                offset += table.len();
+            }
+            records.sort_by_key(|&(tag, _, _, _)| tag);
+            push_directory(&mut directory, TRUETYPE, &records);
+        }
+        header.extend_from_slice(&directory);
+        header.extend_from_slice(&data);
+        header
+    }
+
+    fn head_table() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        // A stale checkSumAdjustment, recomputed on extraction
+        head[8..12].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head
+    }
+
+    #[test]
+    fn extracted_collection_face() {
+        let faces = vec![
+            vec![(tag(b"head"), head_table()), (tag(b"name"), name_table("First", "Regular"))],
+            vec![(tag(b"name"), name_table("Second", "Bold")), (tag(b"head"), head_table()),
+                 (tag(b"DSIG"), vec![0, 0, 0, 1, 0, 0, 0, 0]), (tag(b"cmap"), cmap_table(&[(0x61, 0x63)])),
+                 (tag(b"OS/2"), os2_table(700, 5, 0, 0)[..79].to_vec())],
+        ];
+        let data = collection(&faces);
+        assert_eq!(face_count(&data), 2);
+        let font = extract_face(&data, 1).unwrap();
+
+        assert_eq!(read_u32(&font, 0), Some(TRUETYPE));
+        let count = read_u16(&font, 4).unwrap() as usize;
+        assert_eq!(count, 4);
+        let mut tags = Vec::new();
+        for i in 0..count {
+            let record = 12 + 16 * i;
+            let tag = read_u32(&font, record).unwrap();
+            let offset = read_u32(&font, record + 8).unwrap() as usize;
+            let length = read_u32(&font, record + 12).unwrap() as usize;
+            assert_eq!(offset % 4, 0);
+            let table = &font[offset..offset + length];
+            assert_eq!(read_u32(&font, record + 4), Some(table_checksum(tag, table)));
+            if tag != HEAD {
+                let original = faces[1].iter().find(|&&(original, _)| original == tag).unwrap();
+                assert_eq!(table, &original.1[..]);
+            }
+            tags.push(tag);
+        }
+        assert_eq!(tags, [tag(b"OS/2"), tag(b"cmap"), tag(b"head"), tag(b"name")]);
+        assert_eq!(font.len() % 4, 0);
+        // The checkSumAdjustment makes the whole file sum up to the magic number
+        assert_eq!(checksum(&font), CHECKSUM_MAGIC);
+
+        let parsed = parse_faces(&font);
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].family, "Second");
+        assert_eq!(parsed[0].weight, 700);
+        assert!(parsed[0].coverage.contains('b'));
+        assert_eq!(parse_faces(&extract_face(&data, 0).unwrap())[0].family, "First");
+        // The upper bits of the index name instances of variable fonts
+        assert_eq!(extract_face(&data, 0x10001).unwrap(), font);
+        assert!(matches!(extract_face(&data, 2), Err(FontLoaderError::InvalidFaceIndex(2))));
+        // Single fonts are returned unchanged
+        assert_eq!(extract_face(&font, 0).unwrap(), font);
+        assert!(matches!(extract_face(b"not a font", 0), Err(FontLoaderError::UnsupportedFormat)));
+    }
+
+    #[test]
+    fn read_tables_only() {
+        let outlines = vec![0xAB; 1000];
+        let faces = vec![
+            vec![(tag(b"head"), head_table()), (tag(b"name"), name_table("First", "Regular")),
+                 (tag(b"CFF "), outlines.clone())],
+            vec![(tag(b"name"), name_table("Second", "Bold")), (tag(b"head"), head_table()),
+                 (tag(b"cmap"), cmap_table(&[(0x61, 0x63)])), (tag(b"OS/2"), os2_table(700, 3, 1, 0)),
+                 (tag(b"glyf"), outlines)],
+        ];
+        let data = collection(&faces);
+        let tags = [b"name", b"OS/2", b"head", b"post", b"cmap"];
+        let read = read_faces(&mut io::Cursor::new(&data), &tags).unwrap();
+        assert_eq!(read, parse_faces(&data));
+        assert_eq!(read[0].format, FontFormat::Cff);
+        assert_eq!(read[1].index, 1);
+        assert_eq!(read[1].stretch, Stretch::Condensed);
+
+        // Faces are still parsed, without the tables left out
+        let names = read_faces(&mut io::Cursor::new(&data), &[b"name"]).unwrap();
+        assert_eq!(names[1].postscript_name, "Second-Bold");
+        assert_eq!(names[1].weight, 400);
+
+        let single = extract_face(&data, 1).unwrap();
+        assert_eq!(read_faces(&mut io::Cursor::new(&single), &tags).unwrap(), parse_faces(&single));
+        for truncated in &[&data[..10], &data[..30], &data[..100], &b"wOFF"[..]] {
+            assert_eq!(read_faces(&mut io::Cursor::new(truncated), &tags).unwrap(), parse_faces(truncated));
+        }
+    }
+
+    #[test]
+    fn format4_coverage() {
+        let coverage = parse_cmap(&cmap_table(&[(0x41, 0x43), (0x4E00, 0x4E01)]));
+        for &c in &['A', 'B', 'C', '\u{4E00}', '\u{4E01}'] {
+            assert!(coverage.contains(c), "{:?}", c);
+        }
+        for &c in &['@', 'D', '\u{4DFF}', '\u{4E02}', '\u{FFFF}'] {
+            assert!(!coverage.contains(c), "{:?}", c);
+        }
+
+        // A segment mapping "a" to "c" through the glyph array, where "b" has no glyph
+        let mut subtable = Vec::new();
+        for &value in &[4u16, 0, 0, 4, 0, 0, 0, 0x63, 0xFFFF, 0, 0x61, 0xFFFF, 0, 1, 4, 0, 5, 0, 7] {
+            subtable.extend_from_slice(&value.to_be_bytes());
+        }
+        let coverage = parse_cmap(&cmap(&[(3, 1, subtable)]));
+        assert!(coverage.contains('a'));
+        assert!(!coverage.contains('b'));
+        assert!(coverage.contains('c'));
+    }
+
+    #[test]
+    fn format12_coverage() {
+        let coverage = parse_cmap(&cmap_table(&[(0x61, 0x61), (0x1F600, 0x1F64F)]));
+        assert!(coverage.contains('a'));
+        assert!(coverage.contains('\u{1F600}'));
+        assert!(coverage.contains('\u{1F64F}'));
+        assert!(!coverage.contains('\u{1F650}'));
+        assert!(!coverage.contains('b'));
+
+        // A group starting at glyph 0 maps its first character to .notdef
+        let mut subtable = Vec::new();
+        subtable.extend_from_slice(&[0, 12, 0, 0]);
+        for &value in &[28u32, 0, 1, 0x41, 0x43, 0] {
+            subtable.extend_from_slice(&value.to_be_bytes());
+        }
+        let coverage = parse_cmap(&cmap(&[(3, 10, subtable.clone())]));
+        assert!(!coverage.contains('A'));
+        assert!(coverage.contains('B'));
+        assert!(coverage.contains('C'));
+
+        // The full Unicode subtable is preferred over the Basic Multilingual Plane one
+        let coverage = parse_cmap(&cmap(&[(3, 1, format4(&[(0x61, 0x7A)])), (3, 10, subtable)]));
+        assert!(coverage.contains('B'));
+        assert!(!coverage.contains('a'));
+        // Other platforms and formats are skipped
+        assert!(!parse_cmap(&cmap(&[(1, 0, format4(&[(0x61, 0x7A)]))])).contains('a'));
+        assert!(!parse_cmap(&[0, 0, 0, 5]).contains('a'));
+    }
+
+    #[test]
+    fn coverage_sets() {
+        let coverage = |ranges: &[(u32, u32)]| {
+            let mut coverage = Coverage { ranges: ranges.to_vec() };
+            coverage.normalize();
+            coverage
+        };
+        let mut covered = coverage(&[(0x61, 0x63), (0x41, 0x42), (0x43, 0x44)]);
+        assert_eq!(covered.ranges, [(0x41, 0x44), (0x61, 0x63)]);
+        assert!(coverage(&[(0x42, 0x43)]).is_subset(&covered));
+        assert!(!coverage(&[(0x44, 0x45)]).is_subset(&covered));
+        assert!(Coverage::default().is_subset(&covered));
+
+        covered.union(&coverage(&[(0x45, 0x60)]));
+        assert_eq!(covered.ranges, [(0x41, 0x63)]);
+        assert!(coverage(&[(0x44, 0x45)]).is_subset(&covered));
+    }
+}
@@ -0,0 +1,336 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+#[cfg(not(feature = "fontconfig"))]
+use database::system_font_dirs;
+use error::Result;
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use libc;
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// How long the watcher thread waits for events before checking whether it was dropped
+const POLL_TIMEOUT_MS: libc::c_int = 100;
+
+/// A file or directory which was added, changed or removed in a watched font directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FontChange {
+    path: PathBuf,
+}
+
+impl FontChange {
+    /// The changed file or directory. Empty if the kernel dropped events, so that anything
+    /// may have changed
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Watches font directories with inotify and reports changes from a background thread.
+/// Call `system_fonts::rescan` when notified to pick up the new fonts. The thread is stopped
+/// when the watcher is dropped
+///
+/// ```no_run
+/// extern crate font_loader as fonts;
+///
+/// use fonts::system_fonts;
+/// use fonts::FontWatcher;
+///
+/// fn main() {
+///     let (_watcher, changes) = FontWatcher::channel().unwrap();
+///     for change in changes {
+///         println!("{} changed", change.path().display());
+///         system_fonts::rescan().unwrap();
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FontWatcher {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FontWatcher {
+    /// Watch the font directories and their subdirectories. These are the directories
+    /// fontconfig scans, or those of the XDG Base Directory specification without fontconfig
+    pub fn new<F>(callback: F) -> Result<FontWatcher>
+    where
+        F: FnMut(FontChange) + Send + 'static,
+    {
+        #[cfg(feature = "fontconfig")]
+        let dirs = ::system_fonts::font_dirs()?;
+        #[cfg(not(feature = "fontconfig"))]
+        let dirs = system_font_dirs();
+        FontWatcher::with_dirs(dirs, callback)
+    }
+
+    /// Watch the given directories and their subdirectories. A directory which doesn't exist
+    /// is watched for from its nearest existing parent, and reported once it is created
+    pub fn with_dirs<I, F>(dirs: I, mut callback: F) -> Result<FontWatcher>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+        F: FnMut(FontChange) + Send + 'static,
+    {
+        let mut inotify = Inotify::init()?;
+        let mut watched = Watched {
+            watches: inotify.watches(),
+            dirs: HashMap::new(),
+            parents: HashMap::new(),
+            roots: dirs.into_iter().map(|dir| dir.as_ref().to_path_buf()).collect(),
+        };
+        for dir in watched.roots.clone() {
+            watched.watch_root(&dir);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while !stopped.load(Ordering::Relaxed) {
+                let mut poll_fd = libc::pollfd {
+                    fd: inotify.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                // Timeouts and interrupted calls both just check the stop flag again
+                if unsafe { libc::poll(&mut poll_fd, 1, POLL_TIMEOUT_MS) } <= 0 {
+                    continue;
+                }
+                let events = match inotify.read_events(&mut buffer) {
+                    Ok(events) => events,
+                    Err(ref err) if err.kind() == ::std::io::ErrorKind::WouldBlock => continue,
+                    Err(_) => return,
+                };
+                for event in events {
+                    if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        callback(FontChange { path: PathBuf::new() });
+                        continue;
+                    }
+                    if event.mask.contains(EventMask::IGNORED) {
+                        for path in watched.removed(&event.wd) {
+                            callback(FontChange { path });
+                        }
+                        continue;
+                    }
+                    let path = match watched.path(&event.wd) {
+                        Some(dir) => match event.name {
+                            Some(name) => dir.join(name),
+                            None => dir.to_path_buf(),
+                        },
+                        None => continue,
+                    };
+                    let is_dir = event.mask.contains(EventMask::ISDIR);
+                    let created = is_dir && event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO);
+                    let appeared = created && watched.created_in_parent(&event.wd, &path);
+                    if !watched.dirs.contains_key(&event.wd) {
+                        // Only watched as the parent of a missing directory
+                        if appeared {
+                            callback(FontChange { path });
+                        }
+                        continue;
+                    }
+                    if created {
+                        watched.watch_tree(&path);
+                    } else if event.mask.contains(EventMask::CREATE) {
+                        // Reported once the file is completely written
+                        continue;
+                    }
+                    callback(FontChange { path });
+                }
+            }
+        });
+        Ok(FontWatcher { stop, thread: Some(thread) })
+    }
+
+    /// Watch the font directories like `new`, receiving the changes through a channel. These are
+    /// the directories fontconfig scans, or those of the XDG Base Directory specification without
+    /// fontconfig
+    pub fn channel() -> Result<(FontWatcher, Receiver<FontChange>)> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = FontWatcher::new(move |change| {
+            let _ = sender.send(change);
+        })?;
+        Ok((watcher, receiver))
+    }
+}
+
+impl Drop for FontWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The watches of a watcher thread
+struct Watched {
+    watches: Watches,
+    /// Watched font directories
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Nearest existing parents of missing directories, with the directories missing below them
+    parents: HashMap<WatchDescriptor, (PathBuf, Vec<PathBuf>)>,
+    /// The directories the watcher was created with, watched for again when they are removed
+    roots: Vec<PathBuf>,
+}
+
+impl Watched {
+    fn path(&self, wd: &WatchDescriptor) -> Option<&Path> {
+        self.dirs.get(wd).or_else(|| self.parents.get(wd).map(|(parent, _)| parent)).map(|dir| &**dir)
+    }
+
+    /// Watch a directory and everything below it, or its nearest existing parent until it is
+    /// created. Returns whether the directory exists
+    fn watch_root(&mut self, dir: &Path) -> bool {
+        let parent = match dir.ancestors().find(|ancestor| ancestor.is_dir()) {
+            Some(parent) => parent,
+            None => return false,
+        };
+        if parent == dir {
+            self.watch_tree(dir);
+            return true;
+        }
+        // Added to the watch of a font directory the parent may already have
+        let mask = WatchMask::CREATE | WatchMask::MOVED_TO | WatchMask::ONLYDIR | WatchMask::MASK_ADD;
+        if let Ok(wd) = self.watches.add(parent, mask) {
+            let &mut (_, ref mut missing) =
+                self.parents.entry(wd).or_insert_with(|| (parent.to_path_buf(), Vec::new()));
+            if !missing.iter().any(|missing| missing == dir) {
+                missing.push(dir.to_path_buf());
+            }
+        }
+        false
+    }
+
+    /// Watch a directory and everything below it, skipping what can't be watched
+    fn watch_tree(&mut self, dir: &Path) {
+        let mask = WatchMask::CREATE
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::DELETE
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::ONLYDIR;
+        let wd = match self.watches.add(dir, mask) {
+            Ok(wd) => wd,
+            Err(_) => return,
+        };
+        // Symlinked directories may have been watched already under another path
+        if self.dirs.insert(wd, dir.to_path_buf()).is_some() {
+            return;
+        }
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    self.watch_tree(&entry.path());
+                }
+            }
+        }
+    }
+
+    /// A directory was created in a watched parent, watch the missing directories at or below
+    /// it. Returns whether one of them exists now
+    fn created_in_parent(&mut self, wd: &WatchDescriptor, created: &Path) -> bool {
+        let below: Vec<PathBuf> = match self.parents.get_mut(wd) {
+            Some(&mut (_, ref mut missing)) => {
+                let (below, rest) = missing.drain(..).partition(|dir| dir.starts_with(created));
+                *missing = rest;
+                below
+            }
+            None => return false,
+        };
+        let mut appeared = false;
+        for dir in &below {
+            appeared |= self.watch_root(dir);
+        }
+        if self.parents.get(wd).is_some_and(|(_, missing)| missing.is_empty()) {
+            self.parents.remove(wd);
+            if !self.dirs.contains_key(wd) {
+                let _ = self.watches.remove(wd.clone());
+            }
+        }
+        appeared
+    }
+
+    /// A watch was removed, most likely because its directory was deleted. Returns the
+    /// directories which were created again in the meantime
+    fn removed(&mut self, wd: &WatchDescriptor) -> Vec<PathBuf> {
+        let mut missing = self.parents.remove(wd).map_or_else(Vec::new, |(_, missing)| missing);
+        if let Some(dir) = self.dirs.remove(wd) {
+            if self.roots.contains(&dir) {
+                missing.push(dir);
+            }
+        }
+        missing.retain(|dir| self.watch_root(dir));
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+    use std::time::Duration;
+
+    /// Waits for a change of the path, skipping changes of others
+    fn wait_for(changes: &Receiver<FontChange>, path: &Path) -> bool {
+        while let Ok(change) = changes.recv_timeout(Duration::from_secs(5)) {
+            if change.path() == path {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn missing_dir_created() {
+        let base = env::temp_dir().join(format!("font-loader-watch-{}", process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir(&base).unwrap();
+        let fonts = base.join("share/fonts");
+
+        let (sender, changes) = mpsc::channel();
+        let watcher = FontWatcher::with_dirs(&[&fonts], move |change| {
+            let _ = sender.send(change);
+        }).unwrap();
+        fs::create_dir_all(&fonts).unwrap();
+        assert!(wait_for(&changes, &base.join("share")));
+        fs::write(fonts.join("first.ttf"), b"font").unwrap();
+        assert!(wait_for(&changes, &fonts.join("first.ttf")));
+
+        // Watched for again after it is removed
+        fs::remove_dir_all(&fonts).unwrap();
+        fs::create_dir(&fonts).unwrap();
+        assert!(wait_for(&changes, &fonts));
+        fs::write(fonts.join("second.ttf"), b"font").unwrap();
+        assert!(wait_for(&changes, &fonts.join("second.ttf")));
+
+        drop(watcher);
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
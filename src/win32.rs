@@ -24,146 +24,749 @@ pub mod system_fonts {
     use winapi::um::winnt::{PVOID};
     use winapi::um::wingdi::FIXED_PITCH;
     use winapi::um::wingdi::{ENUMLOGFONTEXW, LOGFONTW, OUT_TT_ONLY_PRECIS};
-    use winapi::um::wingdi::FONTENUMPROCW;
-    use winapi::shared::minwindef::{DWORD, LPARAM};
-
+    use winapi::um::wingdi::{FONTENUMPROCW, OUTLINETEXTMETRICW};
+    use winapi::um::wingdi::{NEWTEXTMETRICEXW, NTM_PS_OPENTYPE, GLYPHSET};
+    use winapi::um::winreg::{RegCloseKey, RegEnumValueW, RegOpenKeyExW, RegQueryInfoKeyW};
+    use winapi::um::winreg::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    use winapi::um::winnt::{KEY_READ, REG_SZ};
+    use winapi::shared::minwindef::{DWORD, HKEY, LPARAM};
+    use winapi::shared::windef::HDC;
+    use winapi::shared::winerror::{ERROR_MORE_DATA, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS};
+
+    use std::cmp;
+    use std::collections::{BTreeSet, HashMap};
+    use std::env;
     use std::ptr;
+    use std::slice;
     use std::mem;
     use std::ffi::{OsStr, OsString};
     use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::path::{Path, PathBuf};
+
+    use database::default_families;
+    use error::{FontLoaderError, Result};
+    use handle::{map_file, read_file, Source};
+    use matching::{locate_in_stack, match_face};
+    use sfnt;
+    pub use handle::FontHandle;
+    pub use info::{FontInfo, StyleInfo};
+    pub use property::{Family, FontProperty, FontPropertyBuilder, GenericFamily};
+    pub use property::{FontFormat, Slant, Spacing, Stretch};
+
+    /// Tag of the whole collection for `GetFontData`, in little endian byte order
+    const TTCF_TABLE: DWORD = 0x6663_7474;
+
+    /// Translates the property into a `LOGFONTW`. Only the preferred family is used, and the
+    /// stretch is left out as GDI can't select faces by width
+    fn logfont(property: &FontProperty) -> Result<LOGFONTW> {
+        let mut string: [u16; 32] = [0; 32]; // +1 Null terminator
+        if let Some(family) = family_names(property).first() {
+            let name: &OsStr = family.as_ref();
+            let buffer: Vec<u16> = name.encode_wide().collect();
+            if buffer.len() > 31 {
+                return Err(FontLoaderError::InvalidFamilyName(family.to_string()));
+            }
+            string[..buffer.len()].copy_from_slice(&buffer);
+        }
+        let mut pitch_and_family = 0;
+        if property.spacing() == Some(Spacing::Monospace) {
+            pitch_and_family |= FIXED_PITCH as u8;
+        }
+        Ok(LOGFONTW {
+            lfHeight: 0,
+            lfWidth: 0,
+            lfEscapement: 0,
+            lfOrientation: 0,
+            lfWeight: property.weight() as i32,
+            lfItalic: (property.slant() != Slant::Roman) as u8,
+            lfUnderline: 0,
+            lfStrikeOut: 0,
+            lfCharSet: property.language().map_or(0, charset),
+            lfOutPrecision: OUT_TT_ONLY_PRECIS as u8,
+            lfClipPrecision: 0,
+            lfQuality: 0,
+            lfPitchAndFamily: pitch_and_family,
+            lfFaceName: string,
+        })
+    }
 
-    /// The platform specific font properties
-    pub type FontProperty = LOGFONTW;
+    /// GDI character set covering a BCP 47 language, ANSI for languages without a dedicated one
+    fn charset(tag: &str) -> u8 {
+        let tag = tag.to_ascii_lowercase().replace('_', "-");
+        let primary = tag.split('-').next().unwrap_or("");
+        let charset = match primary {
+            "ja" => wingdi::SHIFTJIS_CHARSET,
+            "ko" => wingdi::HANGUL_CHARSET,
+            "zh" => {
+                let traditional = ["hant", "tw", "hk", "mo"].iter()
+                    .any(|subtag| tag.split('-').skip(1).any(|s| s == *subtag));
+                if traditional {
+                    wingdi::CHINESEBIG5_CHARSET
+                } else {
+                    wingdi::GB2312_CHARSET
+                }
+            }
+            "ru" | "uk" | "be" | "bg" | "sr" | "mk" | "kk" => wingdi::RUSSIAN_CHARSET,
+            "el" => wingdi::GREEK_CHARSET,
+            "tr" => wingdi::TURKISH_CHARSET,
+            "he" | "yi" => wingdi::HEBREW_CHARSET,
+            "ar" | "fa" | "ur" => wingdi::ARABIC_CHARSET,
+            "th" => wingdi::THAI_CHARSET,
+            "vi" => wingdi::VIETNAMESE_CHARSET,
+            "lt" | "lv" | "et" => wingdi::BALTIC_CHARSET,
+            "cs" | "pl" | "sk" | "hu" | "ro" | "hr" | "sl" => wingdi::EASTEUROPE_CHARSET,
+            _ => wingdi::ANSI_CHARSET,
+        };
+        charset as u8
+    }
 
-    /// Builder for FontProperty
-    pub struct FontPropertyBuilder {
-        config: FontProperty,
+    /// The system tracks installed fonts itself and every query sees the current set,
+    /// so there is never anything to rescan
+    pub fn rescan() -> Result<bool> {
+        Ok(false)
     }
 
-    impl FontPropertyBuilder {
-        pub fn new() -> FontPropertyBuilder {
-            let string: [u16; 32] = [0; 32];
-            FontPropertyBuilder {
-                config: FontProperty {
-                    lfHeight: 0,
-                    lfWidth: 0,
-                    lfEscapement: 0,
-                    lfOrientation: 0,
-                    lfWeight: 0,
-                    lfItalic: 0,
-                    lfUnderline: 0,
-                    lfStrikeOut: 0,
-                    lfCharSet: 0,
-                    lfOutPrecision: OUT_TT_ONLY_PRECIS as u8,
-                    lfClipPrecision: 0,
-                    lfQuality: 0,
-                    lfPitchAndFamily: 0,
-                    lfFaceName: string,
-                },
+    /// Get the binary data and index of a specific font
+    /// Note that only truetype fonts are supported. Faces of collections are returned with the
+    /// whole collection and their index in it. Fails with `NoMatch` if the matched font isn't
+    /// in one of the property's formats, if bitmap fonts are requested, or if no family has
+    /// faces of the requested stretch
+    pub fn get(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        if !property.allows_scalable(true) {
+            return Err(FontLoaderError::NoMatch);
+        }
+        if let Some(face) = listed_face(property)? {
+            return Ok((read_file(face.path())?, face.index()));
+        }
+        let config = logfont(&covering_property(property)?)?;
+        unsafe {
+            let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            let hfont = wingdi::CreateFontIndirectW(&config);
+            if hfont.is_null() {
+                wingdi::DeleteDC(hdc);
+                return Err(FontLoaderError::NoMatch);
             }
+            wingdi::SelectObject(hdc, hfont as *mut c_void);
+            // Table 0 of a collection's face starts at its table directory, whose offsets are
+            // relative to the collection. Only the `ttcf` table gives the whole collection
+            let font = match font_data(hdc, TTCF_TABLE, None) {
+                Some(collection) => collection_index(hdc, &collection).map(|index| (collection, index)),
+                None => font_data(hdc, 0, None).map(|data| (data, 0)),
+            };
+            wingdi::DeleteObject(hfont as *mut c_void);
+            wingdi::DeleteDC(hdc);
+            font.filter(|(data, _)| property.allows_format(FontFormat::detect(data)))
+                .ok_or(FontLoaderError::NoMatch)
         }
+    }
 
-        pub fn italic(mut self) -> FontPropertyBuilder {
-            self.config.lfItalic = true as u8;
-            self
+    /// Reads a table of the selected font, or the whole font for table 0, up to `limit` bytes
+    unsafe fn font_data(hdc: HDC, table: DWORD, limit: Option<usize>) -> Option<Vec<u8>> {
+        let mut size = wingdi::GetFontData(hdc, table, 0, ptr::null_mut(), 0);
+        if size == wingdi::GDI_ERROR as DWORD || size == 0 {
+            return None;
         }
+        if let Some(limit) = limit {
+            size = size.min(limit as DWORD);
+        }
+        let mut buffer: Vec<u8> = vec![0; size as usize];
+        let size = wingdi::GetFontData(hdc, table, 0, buffer.as_mut_ptr() as PVOID, size);
+        if size == wingdi::GDI_ERROR as DWORD {
+            return None;
+        }
+        buffer.truncate(size as usize);
+        Some(buffer)
+    }
+
+    /// Index of the selected face in its collection, found by its table directory
+    unsafe fn collection_index(hdc: HDC, collection: &[u8]) -> Option<c_int> {
+        let header = font_data(hdc, 0, Some(12))?;
+        let count = sfnt::read_u16(&header, 4)? as usize;
+        let directory = font_data(hdc, 0, Some(12 + 16 * count))?;
+        sfnt::face_offsets(collection)
+            .iter()
+            .position(|&offset| collection.get(offset..offset + directory.len()) == Some(&directory[..]))
+            .map(|index| index as c_int)
+    }
 
-        pub fn oblique(self) -> FontPropertyBuilder {
-            self.italic()
+    /// Resolve the file and face index of a specific font without reading it
+    /// The file is looked up by the full name of the face among the registered fonts. Fails with
+    /// `NoMatch` if the matched font isn't in one of the property's formats, if bitmap fonts
+    /// are requested, or if no family has faces of the requested stretch
+    pub fn locate(property: &FontProperty) -> Result<FontHandle> {
+        if !property.allows_scalable(true) {
+            return Err(FontLoaderError::NoMatch);
         }
+        if let Some(face) = listed_face(property)? {
+            return Ok(face.handle());
+        }
+        let config = logfont(&covering_property(property)?)?;
+        let names = unsafe {
+            let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            let hfont = wingdi::CreateFontIndirectW(&config);
+            if hfont.is_null() {
+                wingdi::DeleteDC(hdc);
+                return Err(FontLoaderError::NoMatch);
+            }
+            let old = wingdi::SelectObject(hdc, hfont as *mut c_void);
+            let names = outline_names(hdc);
+            wingdi::SelectObject(hdc, old);
+            wingdi::DeleteObject(hfont as *mut c_void);
+            wingdi::DeleteDC(hdc);
+            names?
+        };
+        let (family, style, full_name) = names;
+        let (path, index) = registered_file(&full_name)?;
+        if !property.formats().is_empty() && !property.allows_format(FontFormat::detect_file(&path)) {
+            return Err(FontLoaderError::NoMatch);
+        }
+        Ok(FontHandle::new(Source::File(path), index, family, style))
+    }
 
-        pub fn monospace(mut self) -> FontPropertyBuilder {
-            self.config.lfPitchAndFamily |= FIXED_PITCH as u8;
-            self
+    /// GDI selects faces by weight and slant only, so a requested style name or stretch is
+    /// looked up among the faces of the families instead. `None` if neither is requested or no
+    /// family has the style, fails with `NoMatch` if no family has faces of the stretch
+    fn listed_face(property: &FontProperty) -> Result<Option<FontInfo>> {
+        if let Some(style) = property.style() {
+            for single in property.family_stack(resolve_generic) {
+                let styled = query_faces(&single)?
+                    .into_iter()
+                    .find(|face| face.style().eq_ignore_ascii_case(style) && has_stretch(face, property));
+                if styled.is_some() {
+                    return Ok(styled);
+                }
+            }
+        }
+        if property.stretch() == Stretch::Normal {
+            return Ok(None);
+        }
+        // Without families, the faces of all installed families are listed
+        let stack = if property.families().is_empty() {
+            vec![property.clone()]
+        } else {
+            property.family_stack(resolve_generic)
+        };
+        for single in &stack {
+            let mut faces = query_faces(single)?;
+            faces.retain(|face| has_stretch(face, property));
+            if let Some(face) = match_face(&faces, single) {
+                return Ok(Some(face.clone()));
+            }
         }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Whether the face has the requested stretch. Normal requests take any face, as GDI
+    /// families usually hold a single width
+    fn has_stretch(face: &FontInfo, property: &FontProperty) -> bool {
+        property.stretch() == Stretch::Normal || face.stretch == property.stretch()
+    }
 
-        pub fn bold(mut self) -> FontPropertyBuilder {
-            self.config.lfWeight = 700;
-            self
+    /// Family, style and full name of the font selected into the device context
+    unsafe fn outline_names(hdc: HDC) -> Result<(String, String, String)> {
+        let size = wingdi::GetOutlineTextMetricsW(hdc, 0, ptr::null_mut());
+        if size == 0 {
+            return Err(FontLoaderError::NoMatch);
         }
+        // u64 storage keeps the buffer aligned for OUTLINETEXTMETRICW
+        let mut buffer: Vec<u64> = vec![0; (size as usize + 7) / 8];
+        let metrics = buffer.as_mut_ptr() as *mut OUTLINETEXTMETRICW;
+        if wingdi::GetOutlineTextMetricsW(hdc, size, metrics) == 0 {
+            return Err(FontLoaderError::NoMatch);
+        }
+        let base = metrics as *const u8;
+        let family = wide_at(base, (*metrics).otmpFamilyName as usize);
+        let style = wide_at(base, (*metrics).otmpStyleName as usize);
+        let full_name = wide_at(base, (*metrics).otmpFaceName as usize);
+        Ok((family, style, full_name))
+    }
 
-        pub fn family(mut self, name: &str) -> FontPropertyBuilder {
-            if name.len() > 31 {
-                panic!("Font length must me smaller than 31");
+    /// Reads the NUL terminated wide string stored at `offset` bytes past `base`
+    unsafe fn wide_at(base: *const u8, offset: usize) -> String {
+        let start = base.add(offset) as *const u16;
+        let mut len = 0;
+        while *start.add(len) != 0 {
+            len += 1;
+        }
+        let name = slice::from_raw_parts(start, len);
+        OsString::from_wide(name).to_string_lossy().into_owned()
+    }
+
+    /// Looks up the file of a face by its full name in the per-user and system font registry.
+    /// Collections are registered as "Face A & Face B (TrueType)", the position of the
+    /// matching face is its index within the file
+    fn registered_file(full_name: &str) -> Result<(PathBuf, c_int)> {
+        for (name, path) in registered_fonts() {
+            let faces = match name.rfind(" (") {
+                Some(pos) => &name[..pos],
+                None => &name[..],
+            };
+            let position = faces.split(" & ")
+                .position(|face| face.eq_ignore_ascii_case(full_name));
+            if let Some(index) = position {
+                return Ok((path, index as c_int));
             }
-            let name: &OsStr = name.as_ref();
-            let buffer = name.encode_wide();
-            let mut string: [u16; 32] = [0; 32]; // +1 Null terminator
-            for (index, item) in buffer.enumerate() {
-                string[index] = item;
+        }
+        Err(FontLoaderError::MissingProperty("file"))
+    }
+
+    /// Names and files of the fonts in the per-user and system font registry, per-user first
+    fn registered_fonts() -> Vec<(String, PathBuf)> {
+        let fonts_key = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Fonts";
+        let mut fonts = Vec::new();
+        for root in &[HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE] {
+            for (name, file) in registry_values(*root, fonts_key) {
+                let mut path = PathBuf::from(&file);
+                if path.is_relative() {
+                    let windir = env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+                    path = PathBuf::from(windir).join("Fonts").join(file);
+                }
+                fonts.push((name, path));
             }
-            self.config.lfFaceName = string;
-            self
         }
+        fonts
+    }
 
-        pub fn build(self) -> FontProperty {
-            self.config
+    /// All string values of a registry key as (name, data) pairs
+    fn registry_values(root: HKEY, key: &str) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        let key: Vec<u16> = OsStr::new(key).encode_wide().chain(Some(0)).collect();
+        unsafe {
+            let mut hkey: HKEY = ptr::null_mut();
+            if RegOpenKeyExW(root, key.as_ptr(), 0, KEY_READ, &mut hkey) != ERROR_SUCCESS as i32 {
+                return values;
+            }
+            // Buffers fitting the longest name and data, so that no entry ends the enumeration
+            let mut max_name_len: DWORD = 0;
+            let mut max_data_len: DWORD = 0;
+            RegQueryInfoKeyW(hkey,
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             ptr::null_mut(),
+                             &mut max_name_len,
+                             &mut max_data_len,
+                             ptr::null_mut(),
+                             ptr::null_mut());
+            let mut name: Vec<u16> = vec![0; max_name_len as usize + 1];
+            let mut data: Vec<u16> = vec![0; max_data_len as usize / 2 + 1];
+            let mut index = 0;
+            loop {
+                let mut name_len = name.len() as DWORD;
+                let mut data_len = (data.len() * 2) as DWORD;
+                let mut kind: DWORD = 0;
+                let status = RegEnumValueW(hkey,
+                                           index,
+                                           name.as_mut_ptr(),
+                                           &mut name_len,
+                                           ptr::null_mut(),
+                                           &mut kind,
+                                           data.as_mut_ptr() as *mut u8,
+                                           &mut data_len);
+                if status == ERROR_NO_MORE_ITEMS as i32 {
+                    break;
+                }
+                if status == ERROR_MORE_DATA as i32 {
+                    // Values added since the key was queried, read again with larger buffers
+                    name.resize(name.len() * 2, 0);
+                    data.resize(cmp::max(data.len() * 2, data_len as usize / 2 + 1), 0);
+                    continue;
+                }
+                index += 1;
+                if status != ERROR_SUCCESS as i32 || kind != REG_SZ {
+                    continue;
+                }
+                let data = &data[..data_len as usize / 2];
+                let end = data.iter().position(|c| *c == 0).unwrap_or(data.len());
+                let name = OsString::from_wide(&name[..name_len as usize]);
+                let data = OsString::from_wide(&data[..end]);
+                values.push((name.to_string_lossy().into_owned(), data.to_string_lossy().into_owned()));
+            }
+            RegCloseKey(hkey);
         }
+        values
     }
 
-    /// Get the binary data and index of a specific font
-    /// Note that only truetype fonts are supported
-    pub fn get(config: &FontProperty) -> Option<(Vec<u8>, c_int)> {
+    /// Find the best installed face containing the character, preferring faces close to the
+    /// requested properties. Only characters of the Basic Multilingual Plane are supported
+    pub fn fallback_for(c: char, property: &FontProperty) -> Result<FontHandle> {
+        let mut property = property.clone();
+        property.chars.insert(c);
+        locate(&property)
+    }
+
+    /// Narrows the families down to the first one containing the requested characters,
+    /// trying all installed families after the requested ones
+    fn covering_property(property: &FontProperty) -> Result<FontProperty> {
+        let mut property = property.clone();
+        if property.chars.is_empty() {
+            return Ok(property);
+        }
+        let mut any = property.clone();
+        any.families.clear();
+        any.chars.clear();
+        let mut candidates = family_names(&property);
+        candidates.extend(query_specific(&any)?);
+        for family in candidates {
+            if family_covers(&property, &family)? {
+                property.families = vec![Family::Name(family)];
+                return Ok(property);
+            }
+        }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Whether the family contains all requested characters. GDI only reports the coverage of
+    /// the Basic Multilingual Plane, other characters are never covered
+    fn family_covers(property: &FontProperty, family: &str) -> Result<bool> {
+        let mut single = property.clone();
+        single.families = vec![Family::Name(family.to_string())];
+        let config = logfont(&single)?;
         unsafe {
             let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
-            let hfont = wingdi::CreateFontIndirectW(config as *const LOGFONTW);
-            wingdi::SelectObject(hdc, hfont as *mut c_void);
-            let size = wingdi::GetFontData(hdc, 0, 0, ptr::null_mut(), 0);
-            if size == 0xFFFFFFFF {
-                wingdi::DeleteDC(hdc);
-                None
-            } else if size > 0 {
-                let mut buffer: Vec<u8> = vec![0; size as usize];
-                let pointer = buffer.first_mut().unwrap() as *mut _ as PVOID;
-                let size = wingdi::GetFontData(hdc, 0, 0, pointer, size);
-                buffer.set_len(size as usize);
-                wingdi::DeleteDC(hdc);
-                Some((buffer, 0))
-            } else {
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            let hfont = wingdi::CreateFontIndirectW(&config);
+            if hfont.is_null() {
                 wingdi::DeleteDC(hdc);
-                None
+                return Ok(false);
             }
+            let old = wingdi::SelectObject(hdc, hfont as *mut c_void);
+            let covered = font_covers(hdc, property.chars());
+            wingdi::SelectObject(hdc, old);
+            wingdi::DeleteObject(hfont as *mut c_void);
+            wingdi::DeleteDC(hdc);
+            Ok(covered)
+        }
+    }
+
+    /// Whether the font selected into the device context contains all characters
+    unsafe fn font_covers(hdc: HDC, chars: &BTreeSet<char>) -> bool {
+        let size = wingdi::GetFontUnicodeRanges(hdc, ptr::null_mut());
+        if size == 0 {
+            return false;
+        }
+        // u64 storage keeps the buffer aligned for GLYPHSET
+        let mut buffer: Vec<u64> = vec![0; (size as usize + 7) / 8];
+        let glyphset = buffer.as_mut_ptr() as *mut GLYPHSET;
+        if wingdi::GetFontUnicodeRanges(hdc, glyphset) == 0 {
+            return false;
         }
+        let ranges = slice::from_raw_parts((*glyphset).ranges.as_ptr(), (*glyphset).cRanges as usize);
+        chars.iter().all(|c| {
+            let c = *c as u32;
+            ranges.iter().any(|range| {
+                let low = u32::from(range.wcLow);
+                c >= low && c < low + u32::from(range.cGlyphs)
+            })
+        })
     }
 
-    pub fn get_native(config: &mut FontProperty) -> FontProperty {
+    /// Get the first native `LOGFONTW` enumerated for the property. Fails with `NoMatch` if no
+    /// family has faces of the requested stretch
+    pub fn get_native(property: &FontProperty) -> Result<LOGFONTW> {
+        let mut property = property.clone();
+        if property.stretch() != Stretch::Normal {
+            if let Some(face) = listed_face(&property)? {
+                property.families = vec![Family::Name(face.family)];
+            }
+        }
+        let mut config = logfont(&property)?;
         let f: FONTENUMPROCW = Some(callback_native);
         unsafe {
-            let mut logfont: LOGFONTW = mem::zeroed();
-            let pointer = &mut logfont as *mut _;
+            let mut native: LOGFONTW = mem::zeroed();
+            let pointer = &mut native as *mut _;
             let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
-            wingdi::EnumFontFamiliesExW(hdc, config, f, pointer as LPARAM, 0);
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            wingdi::EnumFontFamiliesExW(hdc, &mut config, f, pointer as LPARAM, 0);
             wingdi::DeleteDC(hdc);
-            logfont
+            Ok(native)
         }
     }
 
     /// Query the names of all fonts installed in the system
     /// Note that only truetype fonts are supported
-    pub fn query_all() -> Vec<String> {
-        let mut config = FontPropertyBuilder::new().build();
-        query_specific(&mut config)
+    pub fn query_all() -> Result<Vec<String>> {
+        let property = FontPropertyBuilder::new().build()?;
+        query_specific(&property)
     }
 
     /// Query the names of specifc fonts installed in the system
-    /// Note that only truetype fonts are supported
-    pub fn query_specific(property: &mut FontProperty) -> Vec<String> {
-
+    /// Note that only truetype fonts are supported. Families are only listed for a stretch
+    /// other than normal if they have faces of it
+    pub fn query_specific(property: &FontProperty) -> Result<Vec<String>> {
+        if !property.allows_scalable(true) {
+            return Ok(Vec::new());
+        }
+        let mut config = logfont(property)?;
         let mut fonts = Vec::new();
         let mut f: FONTENUMPROCW = Some(callback_ttf);
         unsafe {
             let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
 
-            if (property.lfPitchAndFamily & FIXED_PITCH as u8) != 0 {
+            if (config.lfPitchAndFamily & FIXED_PITCH as u8) != 0 {
                 f = Some(callback_monospace);
             }
 
             let vec_pointer = &mut fonts as *mut Vec<String>;
 
-            wingdi::EnumFontFamiliesExW(hdc, property, f, vec_pointer as LPARAM, 0);
+            wingdi::EnumFontFamiliesExW(hdc, &mut config, f, vec_pointer as LPARAM, 0);
             wingdi::DeleteDC(hdc);
         }
-        fonts
+        if !property.chars().is_empty() {
+            let mut covered = Vec::new();
+            for family in fonts {
+                if family_covers(property, &family)? {
+                    covered.push(family);
+                }
+            }
+            fonts = covered;
+        }
+        if !property.formats().is_empty() {
+            let mut allowed = Vec::new();
+            for family in fonts {
+                let single = FontPropertyBuilder::new().family(&family).build()?;
+                if query_faces(&single)?.iter().any(|face| property.allows_format(face.format)) {
+                    allowed.push(family);
+                }
+            }
+            fonts = allowed;
+        }
+        if property.stretch() != Stretch::Normal {
+            let mut stretched = Vec::new();
+            for family in fonts {
+                let single = FontPropertyBuilder::new().family(&family).build()?;
+                if query_faces(&single)?.iter().any(|face| has_stretch(face, property)) {
+                    stretched.push(family);
+                }
+            }
+            fonts = stretched;
+        }
+        Ok(fonts)
+    }
+
+    /// Query every face of the fonts matching the property's families and spacing.
+    /// Weight, slant and stretch are ignored, so that all styles of a family are returned.
+    /// Faces whose file isn't registered are skipped. GDI doesn't report widths, so the stretch
+    /// of each face is read from its file
+    pub fn query_faces(property: &FontProperty) -> Result<Vec<FontInfo>> {
+        let mut families = if property.families().is_empty() {
+            query_specific(property)?
+        } else {
+            family_names(property)
+        };
+        if !property.chars().is_empty() {
+            let mut covered = Vec::new();
+            for family in families {
+                if family_covers(property, &family)? {
+                    covered.push(family);
+                }
+            }
+            families = covered;
+        }
+        let monospace = property.spacing() == Some(Spacing::Monospace);
+
+        let mut entries: Vec<FaceEntry> = Vec::new();
+        unsafe {
+            let hdc = wingdi::CreateCompatibleDC(ptr::null_mut());
+            if hdc.is_null() {
+                return Err(FontLoaderError::BackendInit);
+            }
+            for family in &families {
+                let single = FontPropertyBuilder::new().family(family).build()?;
+                let mut config = logfont(&single)?;
+                let pointer = &mut entries as *mut Vec<FaceEntry>;
+                wingdi::EnumFontFamiliesExW(hdc, &mut config, Some(callback_faces), pointer as LPARAM, 0);
+            }
+            wingdi::DeleteDC(hdc);
+        }
+
+        let mut stretches: HashMap<PathBuf, Vec<Stretch>> = HashMap::new();
+        let mut faces = Vec::new();
+        for entry in entries {
+            let logfont = &entry.logfont.elfLogFont;
+            let mono = (logfont.lfPitchAndFamily & FIXED_PITCH as u8) != 0;
+            if monospace && !mono {
+                continue;
+            }
+            let full_name = wide_string(&entry.logfont.elfFullName);
+            let (path, index) = match registered_file(&full_name) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            let stretch = stretches.entry(path.clone())
+                .or_insert_with(|| face_stretches(&path))
+                .get(index as usize)
+                .copied()
+                .unwrap_or(Stretch::Normal);
+            faces.push(FontInfo {
+                family: wide_string(&logfont.lfFaceName),
+                style: wide_string(&entry.logfont.elfStyle),
+                full_name,
+                postscript_name: String::new(),
+                weight: logfont.lfWeight as u16,
+                slant: if logfont.lfItalic != 0 { Slant::Italic } else { Slant::Roman },
+                stretch,
+                spacing: if mono { Spacing::Monospace } else { Spacing::Proportional },
+                source: Source::File(path),
+                index,
+                format: Some(if (entry.flags & NTM_PS_OPENTYPE) != 0 { FontFormat::Cff } else { FontFormat::TrueType }),
+                scalable: true,
+                pixel_size: None,
+            });
+        }
+        faces.sort_by(|a, b| {
+            (&a.family, a.stretch, a.weight, a.slant, a.path(), a.index)
+                .cmp(&(&b.family, b.stretch, b.weight, b.slant, b.path(), b.index))
+        });
+        faces.dedup();
+        faces.retain(|face| property.allows_format(face.format) && property.allows_scalable(face.scalable));
+        Ok(faces)
+    }
+
+    /// Stretch of each face of a font file from the `usWidthClass` of its OS/2 table. Normal
+    /// for faces without one, empty if the file can't be read
+    fn face_stretches(path: &Path) -> Vec<Stretch> {
+        let data = match map_file(path) {
+            Ok(data) => data,
+            Err(_) => return Vec::new(),
+        };
+        let data = &data[..];
+        sfnt::face_offsets(data)
+            .into_iter()
+            .map(|offset| {
+                sfnt::table(data, offset, b"OS/2")
+                    .and_then(|os2| sfnt::read_u16(os2, 6))
+                    .map_or(Stretch::Normal, sfnt::stretch_from_class)
+            })
+            .collect()
+    }
+
+    /// A face reported by `EnumFontFamiliesExW`
+    struct FaceEntry {
+        logfont: ENUMLOGFONTEXW,
+        flags: DWORD,
+    }
+
+    /// The styles the installed family provides, e.g. to let users pick one by name for
+    /// `FontPropertyBuilder::style`
+    pub fn styles_of(family: &str) -> Result<Vec<StyleInfo>> {
+        let property = FontPropertyBuilder::new().family(family).build()?;
+        Ok(StyleInfo::of_faces(&query_faces(&property)?))
+    }
+
+    /// Get the binary data and index of the first family of the property which is installed,
+    /// as CSS walks a font stack. Unlike `get`, no other family is substituted, so this fails
+    /// with `NoMatch` if none of them is installed
+    pub fn get_first_available(property: &FontProperty) -> Result<(Vec<u8>, c_int)> {
+        if property.families().is_empty() {
+            return get(property);
+        }
+        for single in property.family_stack(resolve_generic) {
+            if query_faces(&single)?.iter().any(|face| has_stretch(face, property)) {
+                return get(&single);
+            }
+        }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Resolve the file and face index of the face CSS Fonts Level 4 font matching selects,
+    /// among the faces of the first family of the property which is installed. Unlike
+    /// `locate`, the result doesn't depend on the platform
+    pub fn locate_css(property: &FontProperty) -> Result<FontHandle> {
+        locate_in_stack(property, resolve_generic, || locate(property), query_faces)
+    }
+
+    /// Get the binary data and index of the installed face with the PostScript name, such as
+    /// `Roboto-BoldItalic`, as documents reference fonts. GDI doesn't match PostScript names,
+    /// so the name tables of the registered font files are read. No other face is
+    /// substituted, names are compared ignoring ASCII case
+    pub fn get_by_postscript_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        if name.is_empty() {
+            return Err(FontLoaderError::NoMatch);
+        }
+        for (_, path) in registered_fonts() {
+            let data = match map_file(&path) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            let found = sfnt::parse_faces(data.as_ref())
+                .into_iter()
+                .find(|face| face.postscript_name.eq_ignore_ascii_case(name));
+            if let Some(face) = found {
+                return Ok((data.as_ref().to_vec(), face.index as c_int));
+            }
+        }
+        Err(FontLoaderError::NoMatch)
+    }
+
+    /// Get the binary data and index of the installed face with the full name, such as
+    /// `Roboto Bold Italic`, as registered with the system. No other face is substituted,
+    /// names are compared ignoring ASCII case
+    pub fn get_by_full_name(name: &str) -> Result<(Vec<u8>, c_int)> {
+        let (path, index) = registered_file(name).map_err(|_| FontLoaderError::NoMatch)?;
+        Ok((read_file(&path)?, index))
+    }
+
+    /// Resolve a generic family to the installed families commonly used for it, most
+    /// preferred first
+    pub fn resolve_generic(generic: GenericFamily) -> Vec<String> {
+        let installed = query_all().unwrap_or_default();
+        default_families(generic)
+            .iter()
+            .filter_map(|candidate| installed.iter().find(|family| family.eq_ignore_ascii_case(candidate)))
+            .cloned()
+            .collect()
+    }
+
+    /// The requested family names, with generic families resolved
+    fn family_names(property: &FontProperty) -> Vec<String> {
+        property.resolve_generics(resolve_generic)
+            .families
+            .iter()
+            .map(|family| family.name().to_string())
+            .collect()
+    }
+
+    fn wide_string(buffer: &[u16]) -> String {
+        let end = buffer.iter().position(|c| *c == 0).unwrap_or(buffer.len());
+        OsString::from_wide(&buffer[..end]).to_string_lossy().into_owned()
+    }
+
+    #[allow(non_snake_case)]
+    unsafe extern "system" fn callback_faces(lpelfe: *const LOGFONTW,
+                                             lpntme: *const TEXTMETRICW,
+                                             fonttype: DWORD,
+                                             lparam: LPARAM)
+                                             -> c_int {
+        if fonttype != 4 {
+            return 1;
+        }
+
+        let logfont = *(lpelfe as *const ENUMLOGFONTEXW);
+        if logfont.elfLogFont.lfFaceName[0] == '@' as u16 {
+            return 1;
+        }
+        let flags = (*(lpntme as *const NEWTEXTMETRICEXW)).ntmTm.ntmFlags;
+        let entries = &mut *(lparam as *mut Vec<FaceEntry>);
+        entries.push(FaceEntry { logfont, flags });
+
+        1
     }
 
     #[allow(non_snake_case)]
@@ -204,10 +807,10 @@ pub mod system_fonts {
         let lpelfe = lpelfe as *const ENUMLOGFONTEXW;
 
         let name_array = (*lpelfe).elfFullName;
-        let pos = name_array.iter().position(|c| *c == 0).unwrap();
+        let pos = name_array.iter().position(|c| *c == 0).unwrap_or(name_array.len());
         let name_array = &name_array[0..pos];
 
-        let name = OsString::from_wide(name_array).into_string().unwrap();
+        let name = OsString::from_wide(name_array).to_string_lossy().into_owned();
 
         if name.chars().next() != Some('@') {
             let vec_pointer = lparam as *mut Vec<String>;
@@ -0,0 +1,958 @@
+// The MIT License (MIT)
+// Copyright (c) font-loader Developers
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+// associated documentation files (the "Software"), to deal in the Software without restriction,
+// including without limitation the rights to use, copy, modify, merge, publish, distribute,
+// sublicense, and/or sell copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or
+// substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT
+// NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM,
+// DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+//! Decoding of WOFF and WOFF2 web fonts into the TrueType and OpenType fonts they wrap
+
+use error::{FontLoaderError, Result};
+use sfnt::{self, read_u16, read_u32};
+
+use brotli_decompressor::Decompressor;
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
+
+const WOFF: u32 = 0x774F_4646; // "wOFF"
+const WOFF2: u32 = 0x774F_4632; // "wOF2"
+const TTCF: u32 = 0x7474_6366; // "ttcf"
+
+const GLYF: u32 = 0x676C_7966; // "glyf"
+const LOCA: u32 = 0x6C6F_6361; // "loca"
+const HHEA: u32 = 0x6868_6561; // "hhea"
+const HMTX: u32 = 0x686D_7478; // "hmtx"
+
+/// Decoded fonts are limited to this size, so that small files can't exhaust the memory
+const MAX_SIZE: usize = 256 << 20;
+
+/// Tags of the WOFF2 table directory's known tag indices
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+// Flags of composite glyph components
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+// Flags of simple glyph points
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const REPEAT_FLAG: u8 = 0x08;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// Whether the data is a WOFF or WOFF2 font
+pub(crate) fn is_woff(data: &[u8]) -> bool {
+    matches!(read_u32(data, 0), Some(WOFF) | Some(WOFF2))
+}
+
+/// Decode a WOFF or WOFF2 font into the TrueType or OpenType font or collection it wraps
+///
+/// Tables transformed by WOFF2, `glyf`, `loca` and `hmtx`, are reconstructed. Fails with
+/// `UnsupportedFormat` if the data isn't a valid WOFF or WOFF2 font.
+pub fn decode_woff(data: &[u8]) -> Result<Vec<u8>> {
+    let font = match read_u32(data, 0) {
+        Some(WOFF) => decode_woff1(data),
+        Some(WOFF2) => decode_woff2(data),
+        _ => None,
+    };
+    font.ok_or(FontLoaderError::UnsupportedFormat)
+}
+
+fn decode_woff1(data: &[u8]) -> Option<Vec<u8>> {
+    let flavor = read_u32(data, 4)?;
+    let count = read_u16(data, 12)? as usize;
+    let mut tables = Vec::with_capacity(count);
+    let mut total = 0usize;
+    for i in 0..count {
+        let entry = 44 + 20 * i;
+        let tag = read_u32(data, entry)?;
+        let offset = read_u32(data, entry + 4)? as usize;
+        let compressed_length = read_u32(data, entry + 8)? as usize;
+        let length = read_u32(data, entry + 12)? as usize;
+        total = total.checked_add(length).filter(|&total| total <= MAX_SIZE)?;
+        let compressed = data.get(offset..offset.checked_add(compressed_length)?)?;
+        // Tables which don't get smaller are stored as they are
+        let table = if compressed_length < length {
+            decompress_to_vec_zlib_with_limit(compressed, length).ok()?
+        } else {
+            compressed.to_vec()
+        };
+        if table.len() != length {
+            return None;
+        }
+        tables.push((tag, table));
+    }
+    let tables = tables.iter().map(|(tag, table)| (*tag, &table[..])).collect();
+    Some(sfnt::build_sfnt(flavor, tables))
+}
+
+/// A table of the WOFF2 table directory, located in the decompressed stream
+struct Entry {
+    tag: u32,
+    transformed: bool,
+    offset: usize,
+    length: usize,
+}
+
+fn decode_woff2(data: &[u8]) -> Option<Vec<u8>> {
+    let flavor = read_u32(data, 4)?;
+    let count = read_u16(data, 12)? as usize;
+    let compressed_size = read_u32(data, 20)? as usize;
+
+    let mut header = Cursor::new(data);
+    header.bytes(48)?;
+    let mut entries = Vec::with_capacity(count);
+    let mut stream_size = 0usize;
+    for _ in 0..count {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3F {
+            0x3F => header.u32()?,
+            index => read_u32(KNOWN_TAGS[index as usize], 0)?,
+        };
+        // glyf and loca are transformed by default, other tables only on request
+        let version = flags >> 6;
+        let transformed = if tag == GLYF || tag == LOCA { version == 0 } else { version != 0 };
+        let orig_length = header.base128()? as usize;
+        let length = if transformed { header.base128()? as usize } else { orig_length };
+        entries.push(Entry { tag, transformed, offset: stream_size, length });
+        stream_size = stream_size.checked_add(length).filter(|&size| size <= MAX_SIZE)?;
+    }
+
+    // Fonts given by their flavor and the indices of their tables
+    let fonts: Vec<(u32, Vec<usize>)> = if flavor == TTCF {
+        header.u32()?;
+        let font_count = header.u255()?;
+        let mut fonts = Vec::with_capacity(font_count as usize);
+        for _ in 0..font_count {
+            let table_count = header.u255()?;
+            let flavor = header.u32()?;
+            let indices = (0..table_count)
+                .map(|_| header.u255().map(usize::from).filter(|&index| index < count))
+                .collect::<Option<Vec<usize>>>()?;
+            fonts.push((flavor, indices));
+        }
+        fonts
+    } else {
+        vec![(flavor, (0..count).collect())]
+    };
+
+    let compressed = header.bytes(compressed_size)?;
+    let mut stream = Vec::with_capacity(stream_size);
+    Decompressor::new(compressed, 4096)
+        .take(stream_size as u64)
+        .read_to_end(&mut stream)
+        .ok()?;
+    if stream.len() != stream_size {
+        return None;
+    }
+    let raw = |entry: &Entry| &stream[entry.offset..entry.offset + entry.length];
+
+    let mut tables: Vec<Option<Vec<u8>>> = entries.iter().map(|_| None).collect();
+    // The horizontal metrics are reconstructed from the glyphs' bounding boxes
+    let mut x_mins: HashMap<usize, Vec<i16>> = HashMap::new();
+    for (_, indices) in &fonts {
+        let find = |tag| indices.iter().cloned().find(|&index| entries[index].tag == tag);
+        if let Some(glyf) = find(GLYF) {
+            if entries[glyf].transformed && tables[glyf].is_none() {
+                let loca = find(LOCA)?;
+                let (glyf_table, loca_table, glyph_x_mins) = reconstruct_glyf(raw(&entries[glyf]))?;
+                tables[glyf] = Some(glyf_table);
+                tables[loca] = Some(loca_table);
+                x_mins.insert(glyf, glyph_x_mins);
+            }
+        }
+        if let Some(hmtx) = find(HMTX) {
+            if entries[hmtx].transformed && tables[hmtx].is_none() {
+                let glyph_x_mins = x_mins.get(&find(GLYF)?)?;
+                let num_h_metrics = read_u16(raw(&entries[find(HHEA)?]), 34)? as usize;
+                tables[hmtx] = Some(reconstruct_hmtx(raw(&entries[hmtx]), num_h_metrics, glyph_x_mins)?);
+            }
+        }
+    }
+    for (table, entry) in tables.iter_mut().zip(&entries) {
+        if table.is_none() {
+            // Transforms of other tables aren't defined
+            if entry.transformed {
+                return None;
+            }
+            *table = Some(raw(entry).to_vec());
+        }
+    }
+    let tables: Vec<Vec<u8>> = tables.into_iter().map(Option::unwrap).collect();
+
+    if flavor == TTCF {
+        return Some(build_collection(&fonts, &entries, &tables));
+    }
+    let tables = entries.iter().zip(&tables).map(|(entry, table)| (entry.tag, &table[..])).collect();
+    Some(sfnt::build_sfnt(flavor, tables))
+}
+
+/// Lays out a collection whose fonts share the tables. The `head` tables are left as decoded,
+/// as a shared one can't match the checksum of several fonts
+fn build_collection(fonts: &[(u32, Vec<usize>)], entries: &[Entry], tables: &[Vec<u8>]) -> Vec<u8> {
+    let header_size = 12 + 4 * fonts.len();
+    let directories_size: usize = fonts.iter().map(|(_, indices)| 12 + 16 * indices.len()).sum();
+    let mut offsets = Vec::with_capacity(tables.len());
+    let mut offset = header_size + directories_size;
+    for table in tables {
+        offsets.push(offset);
+        offset += sfnt::padded(table.len());
+    }
+
+    let mut collection = Vec::with_capacity(offset);
+    for &value in &[TTCF, 0x0001_0000, fonts.len() as u32] {
+        collection.extend_from_slice(&value.to_be_bytes());
+    }
+    let mut directory = header_size;
+    for (_, indices) in fonts {
+        collection.extend_from_slice(&(directory as u32).to_be_bytes());
+        directory += 12 + 16 * indices.len();
+    }
+    for &(flavor, ref indices) in fonts {
+        let mut records: Vec<(u32, u32, usize, usize)> = indices
+            .iter()
+            .map(|&index| {
+                let (tag, table) = (entries[index].tag, &tables[index]);
+                (tag, sfnt::table_checksum(tag, table), offsets[index], table.len())
+            })
+            .collect();
+        records.sort_by_key(|&(tag, _, _, _)| tag);
+        sfnt::push_directory(&mut collection, flavor, &records);
+    }
+    for table in tables {
+        collection.extend_from_slice(table);
+        collection.resize(sfnt::padded(collection.len()), 0);
+    }
+    collection
+}
+
+/// Rebuilds the `glyf` and `loca` tables from the transformed `glyf` table. Also returns the
+/// minimum x coordinate of each glyph
+fn reconstruct_glyf(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+    let option_flags = read_u16(data, 2)?;
+    let num_glyphs = read_u16(data, 4)? as usize;
+    let index_format = read_u16(data, 6)?;
+    let mut offset = 36usize;
+    let mut next_stream = |index: usize| {
+        let size = read_u32(data, 8 + 4 * index)? as usize;
+        let stream = data.get(offset..offset.checked_add(size)?)?;
+        offset += size;
+        Some(Cursor::new(stream))
+    };
+    let mut contour_stream = next_stream(0)?;
+    let mut points_stream = next_stream(1)?;
+    let mut flag_stream = next_stream(2)?;
+    let mut glyph_stream = next_stream(3)?;
+    let mut composite_stream = next_stream(4)?;
+    let mut bbox_stream = next_stream(5)?;
+    let mut instruction_stream = next_stream(6)?;
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        Some(data.get(offset..offset + num_glyphs.div_ceil(8))?)
+    } else {
+        None
+    };
+    let bbox_bitmap = bbox_stream.bytes(num_glyphs.div_ceil(32) * 4)?;
+    let bit = |bitmap: &[u8], index: usize| bitmap[index >> 3] & (0x80 >> (index & 7)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut loca_offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for index in 0..num_glyphs {
+        loca_offsets.push(glyf.len());
+        let has_bbox = bit(bbox_bitmap, index);
+        let contour_count = contour_stream.i16()?;
+        if contour_count == 0 {
+            // Empty glyphs have no bounding box
+            if has_bbox {
+                return None;
+            }
+            x_mins.push(0);
+            continue;
+        }
+
+        if contour_count < 0 {
+            // Composite glyphs always come with their bounding box
+            if !has_bbox {
+                return None;
+            }
+            let bbox = bbox_stream.bytes(8)?;
+            glyf.extend_from_slice(&contour_count.to_be_bytes());
+            glyf.extend_from_slice(bbox);
+            x_mins.push(read_u16(bbox, 0)? as i16);
+            let mut has_instructions = false;
+            loop {
+                let flags = composite_stream.u16()?;
+                let mut size = if flags & ARG_1_AND_2_ARE_WORDS != 0 { 6 } else { 4 };
+                if flags & WE_HAVE_A_SCALE != 0 {
+                    size += 2;
+                } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    size += 4;
+                } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    size += 8;
+                }
+                glyf.extend_from_slice(&flags.to_be_bytes());
+                glyf.extend_from_slice(composite_stream.bytes(size)?);
+                has_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+                if flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            if has_instructions {
+                let length = glyph_stream.u255()?;
+                glyf.extend_from_slice(&length.to_be_bytes());
+                glyf.extend_from_slice(instruction_stream.bytes(length as usize)?);
+            }
+        } else {
+            let mut end_points = Vec::with_capacity(contour_count as usize);
+            let mut point_count = 0usize;
+            for _ in 0..contour_count {
+                point_count += points_stream.u255()? as usize;
+                end_points.push(u16::try_from(point_count.checked_sub(1)?).ok()?);
+            }
+            let (mut x, mut y) = (0i32, 0i32);
+            let mut points = Vec::with_capacity(point_count);
+            for _ in 0..point_count {
+                let flag = flag_stream.u8()?;
+                let (dx, dy) = read_triplet(flag & 0x7F, &mut glyph_stream)?;
+                x = x.checked_add(dx)?;
+                y = y.checked_add(dy)?;
+                points.push((x, y, flag & 0x80 == 0));
+            }
+            let instruction_length = glyph_stream.u255()?;
+            let instructions = instruction_stream.bytes(instruction_length as usize)?;
+
+            let bbox: Vec<i16> = if has_bbox {
+                let bbox = bbox_stream.bytes(8)?;
+                (0..4).map(|i| read_u16(bbox, 2 * i).map(|value| value as i16)).collect::<Option<_>>()?
+            } else {
+                let x_min = points.iter().map(|point| point.0).min()?;
+                let y_min = points.iter().map(|point| point.1).min()?;
+                let x_max = points.iter().map(|point| point.0).max()?;
+                let y_max = points.iter().map(|point| point.1).max()?;
+                [x_min, y_min, x_max, y_max]
+                    .iter()
+                    .map(|&value| i16::try_from(value).ok())
+                    .collect::<Option<_>>()?
+            };
+            x_mins.push(bbox[0]);
+
+            glyf.extend_from_slice(&contour_count.to_be_bytes());
+            for value in &bbox {
+                glyf.extend_from_slice(&value.to_be_bytes());
+            }
+            for end_point in &end_points {
+                glyf.extend_from_slice(&end_point.to_be_bytes());
+            }
+            glyf.extend_from_slice(&instruction_length.to_be_bytes());
+            glyf.extend_from_slice(instructions);
+
+            let overlap = overlap_bitmap.is_some_and(|bitmap| bit(bitmap, index));
+            let mut flags = Vec::with_capacity(point_count);
+            let mut xs = Vec::new();
+            let mut ys = Vec::new();
+            let (mut last_x, mut last_y) = (0, 0);
+            for (i, &(x, y, on_curve)) in points.iter().enumerate() {
+                let mut flag = if on_curve { ON_CURVE_POINT } else { 0 };
+                if i == 0 && overlap {
+                    flag |= OVERLAP_SIMPLE;
+                }
+                flag |= push_coordinate(x - last_x, X_SHORT_VECTOR, X_IS_SAME_OR_POSITIVE, &mut xs)?;
+                flag |= push_coordinate(y - last_y, Y_SHORT_VECTOR, Y_IS_SAME_OR_POSITIVE, &mut ys)?;
+                flags.push(flag);
+                last_x = x;
+                last_y = y;
+            }
+            push_flags(&flags, &mut glyf);
+            glyf.extend_from_slice(&xs);
+            glyf.extend_from_slice(&ys);
+        }
+        glyf.resize(sfnt::padded(glyf.len()), 0);
+    }
+    loca_offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(loca_offsets.len() * 4);
+    for offset in loca_offsets {
+        if index_format == 0 {
+            loca.extend_from_slice(&u16::try_from(offset / 2).ok()?.to_be_bytes());
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+    Some((glyf, loca, x_mins))
+}
+
+/// Appends the flags of a simple glyph's points, packing runs of the same flag
+fn push_flags(flags: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < flags.len() {
+        let flag = flags[i];
+        let repeats = flags[i + 1..].iter().take(255).take_while(|&&next| next == flag).count();
+        if repeats > 0 {
+            out.push(flag | REPEAT_FLAG);
+            out.push(repeats as u8);
+        } else {
+            out.push(flag);
+        }
+        i += 1 + repeats;
+    }
+}
+
+/// Appends a coordinate delta of a simple glyph in its shortest form and returns its flags
+fn push_coordinate(delta: i32, short: u8, same_or_positive: u8, out: &mut Vec<u8>) -> Option<u8> {
+    if delta == 0 {
+        Some(same_or_positive)
+    } else if delta.abs() < 256 {
+        out.push(delta.unsigned_abs() as u8);
+        Some(if delta > 0 { short | same_or_positive } else { short })
+    } else {
+        out.extend_from_slice(&i16::try_from(delta).ok()?.to_be_bytes());
+        Some(0)
+    }
+}
+
+/// Reads the coordinate delta of a point, encoded by its flag and up to four bytes
+fn read_triplet(flag: u8, stream: &mut Cursor) -> Option<(i32, i32)> {
+    let sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+    let flag_value = i32::from(flag);
+    let delta = match flag {
+        0..=9 => {
+            let b0 = i32::from(stream.u8()?);
+            (0, sign(flag, ((flag_value & 14) << 7) + b0))
+        }
+        10..=19 => {
+            let b0 = i32::from(stream.u8()?);
+            (sign(flag, (((flag_value - 10) & 14) << 7) + b0), 0)
+        }
+        20..=83 => {
+            let base = flag_value - 20;
+            let b0 = i32::from(stream.u8()?);
+            (sign(flag, 1 + (base & 0x30) + (b0 >> 4)), sign(flag >> 1, 1 + ((base & 0x0C) << 2) + (b0 & 0x0F)))
+        }
+        84..=119 => {
+            let base = flag_value - 84;
+            let b0 = i32::from(stream.u8()?);
+            let b1 = i32::from(stream.u8()?);
+            (sign(flag, 1 + ((base / 12) << 8) + b0), sign(flag >> 1, 1 + (((base % 12) >> 2) << 8) + b1))
+        }
+        120..=123 => {
+            let b0 = i32::from(stream.u8()?);
+            let b1 = i32::from(stream.u8()?);
+            let b2 = i32::from(stream.u8()?);
+            (sign(flag, (b0 << 4) + (b1 >> 4)), sign(flag >> 1, ((b1 & 0x0F) << 8) + b2))
+        }
+        _ => {
+            let dx = i32::from(stream.u16()?);
+            let dy = i32::from(stream.u16()?);
+            (sign(flag, dx), sign(flag >> 1, dy))
+        }
+    };
+    Some(delta)
+}
+
+/// Rebuilds the `hmtx` table, taking left side bearings left out by the transform from the
+/// glyphs' minimum x coordinates
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: usize, x_mins: &[i16]) -> Option<Vec<u8>> {
+    let num_glyphs = x_mins.len();
+    if num_h_metrics == 0 || num_h_metrics > num_glyphs {
+        return None;
+    }
+    let mut stream = Cursor::new(data);
+    let flags = stream.u8()?;
+    let advances = (0..num_h_metrics).map(|_| stream.u16()).collect::<Option<Vec<u16>>>()?;
+    let mut bearings = Vec::with_capacity(num_glyphs);
+    for (index, &x_min) in x_mins.iter().enumerate() {
+        // Bit 0 leaves out the bearings of the proportional glyphs, bit 1 those of the rest
+        let derived = if index < num_h_metrics { flags & 1 != 0 } else { flags & 2 != 0 };
+        bearings.push(if derived { x_min } else { stream.i16()? });
+    }
+
+    let mut hmtx = Vec::with_capacity(2 * num_h_metrics + 2 * num_glyphs);
+    for (index, bearing) in bearings.iter().enumerate() {
+        if let Some(advance) = advances.get(index) {
+            hmtx.extend_from_slice(&advance.to_be_bytes());
+        }
+        hmtx.extend_from_slice(&bearing.to_be_bytes());
+    }
+    Some(hmtx)
+}
+
+/// Reads the big endian values of WOFF2 data in sequence
+struct Cursor<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data, position: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position.checked_add(length)?)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        read_u16(self.bytes(2)?, 0)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|value| value as i16)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        read_u32(self.bytes(4)?, 0)
+    }
+
+    /// Variable length `255UInt16`
+    fn u255(&mut self) -> Option<u16> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => Some(u16::from(self.u8()?) + 506),
+            255 => Some(u16::from(self.u8()?) + 253),
+            code => Some(u16::from(code)),
+        }
+    }
+
+    /// Variable length `UIntBase128`
+    fn base128(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            // Leading zeros and values beyond 32 bits aren't allowed
+            if (i == 0 && byte == 0x80) || value & 0xFE00_0000 != 0 {
+                return None;
+            }
+            value = value << 7 | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    const HEAD: u32 = 0x6865_6164; // "head"
+    const MAXP: u32 = 0x6D61_7870; // "maxp"
+
+    /// Original `glyf` table of the test font: an empty glyph, a square, three points with
+    /// repeated flags, instructions and a bounding box of their own, and a composite glyph
+    const GLYF_TABLE: [u8; 68] = [
+        0x00, 0x01, 0x00, 0x64, 0x00, 0x00, 0x01, 0xF4, 0x02, 0xBC, 0x00, 0x03, 0x00, 0x00,
+        0x33, 0x21, 0x11, 0x21, 0x64, 0x01, 0x90, 0xFE, 0x70, 0x02, 0xBC, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x00, 0x28, 0x00, 0x02, 0x00, 0x02,
+        0xB0, 0x01, 0x3F, 0x02, 0x0A, 0x0A, 0x0A, 0x0A, 0x0A, 0x0A,
+        0xFF, 0xFF, 0x00, 0x6E, 0x00, 0x14, 0x01, 0xFE, 0x02, 0xD0, 0x00, 0x02, 0x00, 0x01,
+        0x0A, 0x14,
+    ];
+
+    /// Short offsets of the glyphs in `GLYF_TABLE`
+    const LOCA_TABLE: [u8; 10] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x1A, 0x00, 0x22];
+
+    /// Two advances, then the bearings of the glyphs, which equal their minimum x
+    const HMTX_TABLE: [u8; 12] = [0x01, 0xF4, 0x00, 0x00, 0x02, 0x58, 0x00, 0x64, 0x00, 0x00, 0x00, 0x6E];
+
+    fn head_table() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        head
+    }
+
+    fn hhea_table() -> Vec<u8> {
+        let mut hhea = vec![0; 36];
+        hhea[0..4].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&2u16.to_be_bytes());
+        hhea
+    }
+
+    fn maxp_table() -> Vec<u8> {
+        vec![0x00, 0x00, 0x50, 0x00, 0x00, 0x04]
+    }
+
+    /// Tables of the test font in WOFF2 directory order, with `glyf` directly before `loca`
+    fn tables() -> Vec<(u32, Vec<u8>)> {
+        vec![
+            (HEAD, head_table()),
+            (HHEA, hhea_table()),
+            (HMTX, HMTX_TABLE.to_vec()),
+            (MAXP, maxp_table()),
+            (GLYF, GLYF_TABLE.to_vec()),
+            (LOCA, LOCA_TABLE.to_vec()),
+        ]
+    }
+
+    fn sfnt_font() -> Vec<u8> {
+        let tables = tables();
+        sfnt::build_sfnt(0x0001_0000, tables.iter().map(|(tag, table)| (*tag, &table[..])).collect())
+    }
+
+    /// `GLYF_TABLE` in the transformed form, split into its seven streams
+    fn transformed_glyf() -> Vec<u8> {
+        let streams: [&[u8]; 7] = [
+            &[0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0xFF, 0xFF],
+            &[4, 3],
+            &[11, 13, 5, 12, 23, 23, 23],
+            &[100, 144, 188, 144, 0, 0x99, 0x99, 0x99, 2],
+            &[0x00, 0x02, 0x00, 0x01, 0x0A, 0x14],
+            &[0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x28, 0x00, 0x28,
+              0x00, 0x6E, 0x00, 0x14, 0x01, 0xFE, 0x02, 0xD0],
+            &[0xB0, 0x01],
+        ];
+        let mut glyf = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00];
+        for stream in &streams {
+            glyf.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+        }
+        for stream in &streams {
+            glyf.extend_from_slice(stream);
+        }
+        glyf
+    }
+
+    /// A brotli stream of uncompressed meta-blocks, which the decoder has to accept like any
+    /// other
+    fn brotli_stored(data: &[u8]) -> Vec<u8> {
+        let mut bits = BitWriter::default();
+        // WBITS 16
+        bits.push(0, 1);
+        for chunk in data.chunks(1 << 16) {
+            // Not last, four nibbles of length, uncompressed
+            bits.push(0, 1);
+            bits.push(0, 2);
+            bits.push(chunk.len() as u32 - 1, 16);
+            bits.push(1, 1);
+            bits.align();
+            bits.bytes.extend_from_slice(chunk);
+        }
+        // Last and empty
+        bits.push(0b11, 2);
+        bits.align();
+        bits.bytes
+    }
+
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        used: u32,
+    }
+
+    impl BitWriter {
+        fn push(&mut self, value: u32, count: u32) {
+            for i in 0..count {
+                if self.used == 0 {
+                    self.bytes.push(0);
+                }
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= (((value >> i) & 1) as u8) << self.used;
+                self.used = (self.used + 1) % 8;
+            }
+        }
+
+        fn align(&mut self) {
+            self.used = 0;
+        }
+    }
+
+    fn base128(mut value: u32) -> Vec<u8> {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        value >>= 7;
+        while value != 0 {
+            bytes.insert(0, (value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes
+    }
+
+    /// A WOFF2 file of the tables, given with their transform version and transformed data.
+    /// `collection` lists the flavor and table indices of each font of a collection
+    fn woff2(flavor: u32, tables: &[(u32, u8, Vec<u8>, usize)], collection: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut directory = Vec::new();
+        let mut stream = Vec::new();
+        for &(tag, version, ref data, orig_length) in tables {
+            let index = KNOWN_TAGS.iter().position(|known| read_u32(&known[..], 0) == Some(tag)).unwrap();
+            directory.push(index as u8 | version << 6);
+            directory.extend(base128(orig_length as u32));
+            let transformed = if tag == GLYF || tag == LOCA { version == 0 } else { version != 0 };
+            if transformed {
+                directory.extend(base128(data.len() as u32));
+            }
+            stream.extend_from_slice(data);
+        }
+        if flavor == TTCF {
+            directory.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+            directory.push(collection.len() as u8);
+            for (font_flavor, indices) in collection {
+                directory.push(indices.len() as u8);
+                directory.extend_from_slice(&font_flavor.to_be_bytes());
+                directory.extend_from_slice(indices);
+            }
+        }
+        let compressed = brotli_stored(&stream);
+
+        let mut font = Vec::new();
+        font.extend_from_slice(&WOFF2.to_be_bytes());
+        font.extend_from_slice(&flavor.to_be_bytes());
+        font.extend_from_slice(&((48 + directory.len() + compressed.len()) as u32).to_be_bytes());
+        font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        font.extend_from_slice(&[0; 2]);
+        font.extend_from_slice(&0u32.to_be_bytes());
+        font.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        font.extend_from_slice(&[0; 24]);
+        font.extend_from_slice(&directory);
+        font.extend_from_slice(&compressed);
+        font
+    }
+
+    /// The test font as WOFF2 with transformed `glyf`, `loca` and `hmtx` tables
+    fn transformed_woff2() -> Vec<u8> {
+        let tables = vec![
+            (HEAD, 0, head_table(), 54),
+            (HHEA, 0, hhea_table(), 36),
+            // Both kinds of bearings are left out
+            (HMTX, 1, vec![0x03, 0x01, 0xF4, 0x02, 0x58], HMTX_TABLE.len()),
+            (MAXP, 0, maxp_table(), 6),
+            (GLYF, 0, transformed_glyf(), GLYF_TABLE.len()),
+            (LOCA, 0, Vec::new(), LOCA_TABLE.len()),
+        ];
+        woff2(0x0001_0000, &tables, &[])
+    }
+
+    /// The test font as WOFF, with tables compressed where that makes them smaller
+    fn woff1() -> Vec<u8> {
+        let tables = tables();
+        let mut directory = Vec::new();
+        let mut data = Vec::new();
+        let mut offset = 44 + 20 * tables.len();
+        for (tag, table) in &tables {
+            let compressed = compress_to_vec_zlib(table, 6);
+            let stored = if compressed.len() < table.len() { compressed } else { table.clone() };
+            for &value in &[*tag, offset as u32, stored.len() as u32, table.len() as u32,
+                            sfnt::table_checksum(*tag, table)] {
+                directory.extend_from_slice(&value.to_be_bytes());
+            }
+            offset += stored.len();
+            data.extend(stored);
+        }
+        let mut font = Vec::new();
+        for &value in &[WOFF, 0x0001_0000, offset as u32] {
+            font.extend_from_slice(&value.to_be_bytes());
+        }
+        font.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        font.extend_from_slice(&[0; 30]);
+        font.extend(directory);
+        font.extend(data);
+        font
+    }
+
+    #[test]
+    fn woff1_round_trip() {
+        let font = woff1();
+        assert!(is_woff(&font));
+        assert_eq!(decode_woff(&font).unwrap(), sfnt_font());
+    }
+
+    #[test]
+    fn woff2_null_transform_round_trip() {
+        let tables: Vec<(u32, u8, Vec<u8>, usize)> = tables()
+            .into_iter()
+            .map(|(tag, table)| {
+                let version = if tag == GLYF || tag == LOCA { 3 } else { 0 };
+                let length = table.len();
+                (tag, version, table, length)
+            })
+            .collect();
+        assert_eq!(decode_woff(&woff2(0x0001_0000, &tables, &[])).unwrap(), sfnt_font());
+    }
+
+    #[test]
+    fn woff2_transformed_round_trip() {
+        let font = decode_woff(&transformed_woff2()).unwrap();
+        assert_eq!(sfnt::table(&font, 0, b"glyf"), Some(&GLYF_TABLE[..]));
+        assert_eq!(sfnt::table(&font, 0, b"loca"), Some(&LOCA_TABLE[..]));
+        assert_eq!(sfnt::table(&font, 0, b"hmtx"), Some(&HMTX_TABLE[..]));
+        assert_eq!(font, sfnt_font());
+    }
+
+    #[test]
+    fn woff2_overlap_bitmap() {
+        let mut glyf = transformed_glyf();
+        glyf[3] = 1;
+        // Only the square overlaps
+        glyf.push(0x40);
+        let tables = vec![
+            (HEAD, 0, head_table(), 54),
+            (GLYF, 0, glyf, GLYF_TABLE.len()),
+            (LOCA, 0, Vec::new(), LOCA_TABLE.len()),
+        ];
+        let font = decode_woff(&woff2(0x0001_0000, &tables, &[])).unwrap();
+        let glyf = sfnt::table(&font, 0, b"glyf").unwrap();
+        assert_eq!(glyf[14], 0x33 | OVERLAP_SIMPLE);
+        assert_eq!(&glyf[15..], &GLYF_TABLE[15..]);
+    }
+
+    #[test]
+    fn woff2_collection() {
+        let tables = vec![
+            (HEAD, 0, head_table(), 54),
+            (HHEA, 0, hhea_table(), 36),
+            (HMTX, 1, vec![0x03, 0x01, 0xF4, 0x02, 0x58], HMTX_TABLE.len()),
+            (MAXP, 0, maxp_table(), 6),
+            (GLYF, 0, transformed_glyf(), GLYF_TABLE.len()),
+            (LOCA, 0, Vec::new(), LOCA_TABLE.len()),
+        ];
+        let fonts = vec![(0x0001_0000, vec![0, 1, 2, 3, 4, 5]), (0x0001_0000, vec![0, 3, 4, 5])];
+        let collection = decode_woff(&woff2(TTCF, &tables, &fonts)).unwrap();
+        let offsets = sfnt::face_offsets(&collection);
+        assert_eq!(offsets.len(), 2);
+        for &offset in &offsets {
+            assert_eq!(sfnt::table(&collection, offset, b"glyf"), Some(&GLYF_TABLE[..]));
+            assert_eq!(sfnt::table(&collection, offset, b"loca"), Some(&LOCA_TABLE[..]));
+        }
+        assert_eq!(sfnt::table(&collection, offsets[0], b"hmtx"), Some(&HMTX_TABLE[..]));
+        assert_eq!(sfnt::table(&collection, offsets[1], b"hmtx"), None);
+    }
+
+    #[test]
+    fn variable_length_numbers() {
+        let mut cursor = Cursor::new(&[252, 253, 0x12, 0x34, 254, 7, 255, 7]);
+        assert_eq!(cursor.u255(), Some(252));
+        assert_eq!(cursor.u255(), Some(0x1234));
+        assert_eq!(cursor.u255(), Some(513));
+        assert_eq!(cursor.u255(), Some(260));
+        assert_eq!(cursor.u255(), None);
+
+        for &value in &[0, 127, 128, 16_383, 0xFFFF_FFFF] {
+            assert_eq!(Cursor::new(&base128(value)).base128(), Some(value));
+        }
+        // Leading zeros, more than 32 bits and more than five bytes
+        assert_eq!(Cursor::new(&[0x80, 0x01]).base128(), None);
+        assert_eq!(Cursor::new(&[0x90, 0x80, 0x80, 0x80, 0x00]).base128(), None);
+        assert_eq!(Cursor::new(&[0x81, 0x80, 0x80, 0x80, 0x80, 0x00]).base128(), None);
+        assert_eq!(Cursor::new(&[0x81]).base128(), None);
+    }
+
+    #[test]
+    fn truncated_fonts() {
+        for font in &[woff1(), transformed_woff2()] {
+            for length in 0..font.len() {
+                assert!(decode_woff(&font[..length]).is_err(), "decoded {} bytes", length);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_fonts() {
+        // No byte changed on its own may panic, whether the result decodes or not
+        for font in &[woff1(), transformed_woff2()] {
+            for index in 0..font.len() {
+                for &mask in &[0x01, 0x80, 0xFF] {
+                    let mut corrupted = font.clone();
+                    corrupted[index] ^= mask;
+                    let _ = decode_woff(&corrupted);
+                }
+            }
+        }
+
+        let mut signature = transformed_woff2();
+        signature[3] = b'3';
+        assert!(decode_woff(&signature).is_err());
+
+        // Table lengths beyond the size limit
+        let mut woff = woff1();
+        woff[44 + 12..44 + 16].copy_from_slice(&0xFFFF_FFFFu32.to_be_bytes());
+        assert!(decode_woff(&woff).is_err());
+
+        // More tables than the file has room for
+        let mut tables = transformed_woff2();
+        tables[12..14].copy_from_slice(&0xFFFFu16.to_be_bytes());
+        assert!(decode_woff(&tables).is_err());
+
+        // Data claimed to be compressed which isn't zlib
+        let mut zlib = woff1();
+        let offset = read_u32(&zlib, 44 + 4).unwrap() as usize;
+        let length = read_u32(&zlib, 44 + 8).unwrap() as usize;
+        assert!(length < read_u32(&zlib, 44 + 12).unwrap() as usize);
+        zlib[offset] ^= 0xFF;
+        assert!(decode_woff(&zlib).is_err());
+    }
+
+    #[test]
+    fn inconsistent_glyf_streams() {
+        let decode_glyf = |glyf: Vec<u8>| {
+            let tables = vec![
+                (GLYF, 0, glyf, GLYF_TABLE.len()),
+                (LOCA, 0, Vec::new(), LOCA_TABLE.len()),
+            ];
+            decode_woff(&woff2(0x0001_0000, &tables, &[]))
+        };
+        assert!(decode_glyf(transformed_glyf()).is_ok());
+
+        // The empty glyph with a bounding box
+        let mut bbox = transformed_glyf();
+        let bitmap = bbox.len() - 2 - 20;
+        bbox[bitmap] |= 0x80;
+        assert!(decode_glyf(bbox).is_err());
+
+        // The composite glyph without one
+        let mut composite = transformed_glyf();
+        composite[bitmap] &= !0x10;
+        assert!(decode_glyf(composite).is_err());
+
+        // A stream longer than the table
+        let mut streams = transformed_glyf();
+        streams[8..12].copy_from_slice(&0xFFFF_FFFFu32.to_be_bytes());
+        assert!(decode_glyf(streams).is_err());
+
+        // A contour without points
+        let mut points = transformed_glyf();
+        points[36 + 8] = 0;
+        assert!(decode_glyf(points).is_err());
+
+        // Transformed hmtx without the glyf table it derives bearings from
+        let tables = vec![
+            (HHEA, 0, hhea_table(), 36),
+            (HMTX, 1, vec![0x03, 0x01, 0xF4, 0x02, 0x58], HMTX_TABLE.len()),
+        ];
+        assert!(decode_woff(&woff2(0x0001_0000, &tables, &[])).is_err());
+    }
+}